@@ -0,0 +1,112 @@
+//! Rules for how many cherries to send for an approval.
+use color_eyre::eyre;
+use serde::Deserialize;
+
+use crate::condition::Condition;
+use crate::github;
+
+/// The `[amount]` config section.
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct Config {
+    /// Cherries to send before applying rules. Defaults to
+    /// `cherries_per_check`.
+    pub base: Option<usize>,
+    /// Never send fewer than this many cherries.
+    #[serde(default = "min_default")]
+    pub min: usize,
+    /// Never send more than this many cherries.
+    pub max: Option<usize>,
+    /// Every matching rule adjusts the amount.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+fn min_default() -> usize {
+    1
+}
+
+/// A rule adding (or subtracting) cherries for approvals of PRs that match
+/// all of its conditions.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(flatten)]
+    pub condition: Condition,
+    pub min_lines_changed: Option<u64>,
+    pub max_lines_changed: Option<u64>,
+    pub min_files_changed: Option<u64>,
+    pub max_files_changed: Option<u64>,
+    /// The reviewer left at least this many review comments.
+    pub min_review_comments: Option<usize>,
+    /// Cherries to add; may be negative.
+    pub add: i64,
+}
+
+/// What rules can match on.
+#[derive(Clone, Debug)]
+pub struct Attributes<'a> {
+    pub pr: &'a github::PullRequest,
+    pub details: &'a github::PullRequestDetails,
+    /// Review comments the reviewer left on the PR.
+    pub review_comments: usize,
+}
+
+impl Rule {
+    fn matches(&self, attrs: &Attributes<'_>) -> bool {
+        let lines = attrs.details.lines_changed();
+        let files = attrs.details.changed_files;
+        self.condition.matches(attrs.pr, Some(attrs.details))
+            && self.min_lines_changed.is_none_or(|min| lines >= min)
+            && self.max_lines_changed.is_none_or(|max| lines <= max)
+            && self.min_files_changed.is_none_or(|min| files >= min)
+            && self.max_files_changed.is_none_or(|max| files <= max)
+            && self
+                .min_review_comments
+                .is_none_or(|min| attrs.review_comments >= min)
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if let Some(max) = self.max {
+            if max < self.min {
+                return Err(eyre::eyre!(
+                    "amount.max ({max}) is less than amount.min ({})",
+                    self.min
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether computing the amount needs PR details from GitHub.
+    pub fn has_rules(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// Whether any rule looks at review comments.
+    pub fn uses_review_comments(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.min_review_comments.is_some())
+    }
+
+    /// Cherries to send for an approval, with `base` filled in.
+    pub fn amount(&self, attrs: Option<Attributes<'_>>) -> usize {
+        let base = self.base.unwrap_or(1) as i64;
+        let adjustment: i64 = match attrs {
+            Some(attrs) => self
+                .rules
+                .iter()
+                .filter(|rule| rule.matches(&attrs))
+                .map(|rule| rule.add)
+                .sum(),
+            None => 0,
+        };
+        let amount = (base + adjustment).max(self.min as i64) as usize;
+        match self.max {
+            Some(max) => amount.min(max),
+            None => amount,
+        }
+    }
+}
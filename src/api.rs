@@ -1,26 +1,41 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::path::PathBuf;
 
 use color_eyre::eyre;
 use serde::Deserialize;
 
+use crate::amount;
 use crate::github;
+use crate::hashtag;
+use crate::reason;
 
 #[derive(Deserialize)]
 pub struct Credentials {
     bonusly: String,
     github: String,
+    /// Map from additional authors' GitHub usernames to their Bonusly tokens.
+    #[serde(default)]
+    authors: HashMap<String, String>,
 }
 
-impl TryFrom<Credentials> for super::Credentials {
+/// Credentials, and the GitHub config for which server to use them with.
+impl TryFrom<(Credentials, &github::Config)> for super::Credentials {
     type Error = eyre::Error;
 
-    fn try_from(value: Credentials) -> Result<Self, Self::Error> {
+    fn try_from((value, github): (Credentials, &github::Config)) -> Result<Self, Self::Error> {
+        let mut builder = octocrab::Octocrab::builder().personal_token(value.github);
+        if let Some(api_url) = github.api_url() {
+            builder = builder.base_url(api_url)?;
+        }
         Ok(Self {
             bonusly: super::bonusly::Client::from_token(value.bonusly),
-            github: octocrab::Octocrab::builder()
-                .personal_token(value.github)
-                .build()?,
+            authors: value
+                .authors
+                .into_iter()
+                .map(|(user, token)| (user, super::bonusly::Client::from_token(token)))
+                .collect(),
+            github: github::Client::new(builder.build()?),
         })
     }
 }
@@ -40,6 +55,33 @@ pub struct Config {
     pub state_update_days: i64,
     #[serde(default = "send_bonus_delay_seconds_default")]
     pub send_bonus_delay_seconds: u64,
+    #[serde(default)]
+    pub spend_down_days: Option<i64>,
+    #[serde(default)]
+    pub batch_bonuses: bool,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub amount: amount::Config,
+    #[serde(default)]
+    pub reasons: reason::Config,
+    #[serde(default)]
+    pub hashtags: hashtag::Config,
+}
+
+/// An additional PR author to send cherries on behalf of. Unset fields
+/// default to the top-level config's.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Author {
+    /// GitHub username; also the key for this author's Bonusly token in the
+    /// credentials file's `[authors]` table.
+    pub user: String,
+    pub cherries_per_check: Option<usize>,
+    pub spend_down_days: Option<i64>,
+    pub batch_bonuses: Option<bool>,
+    pub reasons: Option<reason::Config>,
+    pub hashtags: Option<hashtag::Config>,
 }
 
 fn send_bonus_delay_seconds_default() -> u64 {
@@ -0,0 +1,132 @@
+//! The GitHub and Bonusly APIs that [`Program`](crate::Program) talks to.
+//!
+//! [`ReviewSource`] is implemented by [`github::Client`] and [`RewardSink`] by
+//! [`bonusly::Client`]; see [`crate::fake`] for in-memory implementations to
+//! test with.
+use std::future::Future;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre;
+
+use crate::bonusly;
+use crate::github;
+
+/// Where approved reviews come from.
+pub trait ReviewSource {
+    /// Pull requests matching a GitHub search query, like
+    /// `is:pr author:me review:approved org:acme`.
+    fn search_prs(
+        &self,
+        query: &str,
+    ) -> impl Future<Output = eyre::Result<Vec<github::PullRequest>>> + Send;
+
+    /// Every review on a pull request.
+    fn list_reviews(
+        &self,
+        pr: &github::PullRequest,
+    ) -> impl Future<Output = eyre::Result<Vec<github::Review>>> + Send;
+
+    /// A single review on a pull request.
+    fn review(
+        &self,
+        pr: &github::PullRequest,
+        id: github::ReviewId,
+    ) -> impl Future<Output = eyre::Result<github::Review>> + Send;
+
+    /// A user's profile.
+    fn user(&self, login: &str) -> impl Future<Output = eyre::Result<github::User>> + Send;
+
+    /// Details of a pull request not included in search results.
+    fn pr_details(
+        &self,
+        pr: &github::PullRequest,
+    ) -> impl Future<Output = eyre::Result<github::PullRequestDetails>> + Send;
+
+    /// Review comments left on a pull request.
+    fn review_comments(
+        &self,
+        pr: &github::PullRequest,
+    ) -> impl Future<Output = eyre::Result<Vec<github::ReviewComment>>> + Send;
+}
+
+/// Where bonuses are sent.
+pub trait RewardSink {
+    /// Every user in the company.
+    fn list_users(&self) -> impl Future<Output = eyre::Result<Vec<bonusly::User>>> + Send;
+
+    /// The company's hashtags, including the leading `#`.
+    fn hashtags(&self) -> impl Future<Output = eyre::Result<Vec<String>>> + Send;
+
+    /// The user bonuses are sent from.
+    fn me(&self) -> impl Future<Output = eyre::Result<bonusly::User>> + Send;
+
+    fn send_bonus(
+        &self,
+        bonus: &bonusly::Bonus,
+    ) -> impl Future<Output = eyre::Result<bonusly::BonusReply>> + Send;
+
+    /// Bonuses sent by [`RewardSink::me`] since `since`.
+    fn sent_bonuses(
+        &self,
+        since: DateTime<Utc>,
+    ) -> impl Future<Output = eyre::Result<Vec<bonusly::SentBonus>>> + Send;
+}
+
+impl ReviewSource for github::Client {
+    async fn search_prs(&self, query: &str) -> eyre::Result<Vec<github::PullRequest>> {
+        github::search_prs(self, query).await
+    }
+
+    async fn list_reviews(&self, pr: &github::PullRequest) -> eyre::Result<Vec<github::Review>> {
+        github::list_reviews(self, pr).await
+    }
+
+    async fn review(
+        &self,
+        pr: &github::PullRequest,
+        id: github::ReviewId,
+    ) -> eyre::Result<github::Review> {
+        github::review(self, pr, id).await
+    }
+
+    async fn user(&self, login: &str) -> eyre::Result<github::User> {
+        github::User::from_login(self, login).await
+    }
+
+    async fn pr_details(
+        &self,
+        pr: &github::PullRequest,
+    ) -> eyre::Result<github::PullRequestDetails> {
+        github::PullRequestDetails::fetch(self, pr).await
+    }
+
+    async fn review_comments(
+        &self,
+        pr: &github::PullRequest,
+    ) -> eyre::Result<Vec<github::ReviewComment>> {
+        github::review_comments(self, pr).await
+    }
+}
+
+impl RewardSink for bonusly::Client {
+    async fn list_users(&self) -> eyre::Result<Vec<bonusly::User>> {
+        bonusly::Client::list_users(self).await
+    }
+
+    async fn hashtags(&self) -> eyre::Result<Vec<String>> {
+        bonusly::Client::hashtags(self).await
+    }
+
+    async fn me(&self) -> eyre::Result<bonusly::User> {
+        bonusly::Client::me(self).await
+    }
+
+    async fn send_bonus(&self, bonus: &bonusly::Bonus) -> eyre::Result<bonusly::BonusReply> {
+        bonusly::Client::send_bonus(self, bonus).await
+    }
+
+    async fn sent_bonuses(&self, since: DateTime<Utc>) -> eyre::Result<Vec<bonusly::SentBonus>> {
+        let me = bonusly::Client::me(self).await?;
+        bonusly::Client::sent_bonuses(self, &me.email, since).await
+    }
+}
@@ -0,0 +1,123 @@
+//! Sending cherries for approvals from before the program was installed.
+use std::time::Duration;
+
+use chrono::prelude::*;
+use color_eyre::eyre;
+use tracing::{debug, error, info, instrument};
+
+use crate::{batch_reviews, Config, Program, ReviewSource, ReviewStatus, RewardSink, SendErrors};
+
+/// A window of time to look for approvals in.
+#[derive(Clone, Debug)]
+pub struct Backfill {
+    pub since: DateTime<Utc>,
+    /// Exclusive.
+    pub until: DateTime<Utc>,
+}
+
+impl Backfill {
+    pub fn new(since: DateTime<Utc>, until: Option<DateTime<Utc>>) -> eyre::Result<Self> {
+        let until = until.unwrap_or_else(Utc::now);
+        if since >= until {
+            return Err(eyre::eyre!(
+                "Backfill start {since} isn't before its end {until}"
+            ));
+        }
+        Ok(Self { since, until })
+    }
+
+    /// Whether a review submitted at `submitted_at` is in the window.
+    pub fn contains(&self, submitted_at: Option<DateTime<Utc>>) -> bool {
+        submitted_at.is_some_and(|at| self.since <= at && at < self.until)
+    }
+}
+
+/// Parse a `YYYY-MM-DD` date (midnight UTC) or an RFC 3339 timestamp.
+pub fn parse_datetime(s: &str) -> eyre::Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(DateTime::from_utc(date.and_hms(0, 0, 0), Utc));
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(|_| {
+            eyre::eyre!("Expected a date like 2022-03-01 or 2022-03-01T09:30:00Z, not {s:?}")
+        })
+}
+
+/// The bonuses a backfill would send for one author.
+pub struct BackfillPlan {
+    pub config: Config,
+    pub reviews: Vec<ReviewStatus>,
+    /// Cherries the author has left to give this month, if known.
+    pub giving_balance: Option<usize>,
+}
+
+impl BackfillPlan {
+    /// Cherries the plan would send.
+    pub fn total(&self) -> usize {
+        self.reviews
+            .iter()
+            .map(|review| match review {
+                ReviewStatus::Ok(_, bonus, _) | ReviewStatus::Batch(_, bonus) => bonus.amount,
+                ReviewStatus::MissingEmail(_) => 0,
+            })
+            .sum()
+    }
+}
+
+impl<S: ReviewSource, R: RewardSink> Program<S, R> {
+    /// Find approvals in `backfill`'s window that haven't been replied to,
+    /// for each author. Nothing is sent and the state file isn't written.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn plan_backfill(&mut self, backfill: &Backfill) -> eyre::Result<Vec<BackfillPlan>> {
+        let mut plans = Vec::new();
+        for config in self.config.author_configs() {
+            self.refresh_giving_balance(&config).await?;
+            let mut reviews = self.reviews(&config, Some(backfill)).await?;
+            if config.batch_bonuses {
+                reviews = batch_reviews(reviews);
+            }
+            plans.push(BackfillPlan {
+                giving_balance: self.giving_balance(&config.github.user),
+                config,
+                reviews,
+            });
+        }
+        Ok(plans)
+    }
+
+    /// Send the bonuses in `plans` and write the state file. Reviewers
+    /// without a Bonusly email are saved as pending, like in a regular
+    /// check.
+    ///
+    /// Bonuses that would exceed an author's giving balance are left pending. If
+    /// any bonuses fail to send, the rest are still sent and a
+    /// [`SendErrors`] is returned.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn send_backfill(&mut self, plans: Vec<BackfillPlan>) -> eyre::Result<()> {
+        let mut errors = Vec::new();
+        for plan in plans {
+            for review in plan.reviews {
+                if let Err(err) = self.reply(&plan.config, review).await {
+                    error!("Error while sending cherries: {:?}", err);
+                    errors.push(err);
+                }
+
+                if self.dry_run {
+                    continue;
+                }
+                let duration = Duration::from_secs(10);
+                debug!("Sleeping {:?} before sending next bonus", duration);
+                tokio::time::sleep(duration).await;
+            }
+        }
+        info!(failed = errors.len(), "Finished backfill");
+        self.write_state().await?;
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SendErrors(errors).into())
+        }
+    }
+}
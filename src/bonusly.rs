@@ -1,34 +1,298 @@
 //! See <https://bonusly.docs.apiary.io/>
+use std::fmt::{self, Display};
 use std::time::Duration;
 
-use color_eyre::eyre::{self, Report};
-use reqwest::{Client as HttpClient, RequestBuilder};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{self, Report, WrapErr};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client as HttpClient, RequestBuilder, StatusCode};
 use secrecy::{ExposeSecret, SecretString};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 static BONUSLY_API_URL: &str = "https://bonus.ly/api/v1";
+static APPLICATION_NAME: &str = "cherries-4-prs";
 
-/// A Bonusly client. See [`Client::from_token`].
+/// A Bonusly client. See [`Client::from_token`] and [`Client::builder`].
 pub struct Client {
     token: SecretString,
     client: HttpClient,
+    base_url: String,
+    application_name: String,
+    retry_policy: RetryPolicy,
+}
+
+/// Builds a [`Client`] that doesn't use the defaults.
+pub struct ClientBuilder {
+    token: SecretString,
+    client: Option<HttpClient>,
+    base_url: String,
+    application_name: String,
+    retry_policy: RetryPolicy,
+}
+
+/// How to retry requests that fail transiently: connection errors,
+/// timeouts, rate limiting (429), and server errors (5xx).
+///
+/// Sending a bonus isn't idempotent, so it's only retried when the request
+/// definitely wasn't processed: connection errors and rate limiting. Other
+/// failures may have sent the bonus anyway, and are returned for the caller
+/// to reconcile.
+///
+/// Other errors, like an unknown receiver or an insufficient balance, are
+/// returned immediately as an [`ApiError`].
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    /// Backoff before the first retry, which doubles for each retry after
+    /// that. A `Retry-After` header takes precedence.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 4,
+            initial_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// The backoff before retry number `retry`, counting from 0. Jittered to
+    /// between half and all of the exponential backoff, so that clients
+    /// don't retry in lockstep.
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        let half = backoff / 2;
+        half + half.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// An error response from Bonusly.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    /// Whether the same request might succeed later.
+    pub fn is_transient(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS || self.status.is_server_error()
+    }
+
+    /// Whether Bonusly refused the request outright, so it definitely wasn't
+    /// acted on. Server errors may come from a gateway after Bonusly
+    /// processed the request, so they aren't rejections.
+    pub fn is_rejection(&self) -> bool {
+        self.status.is_client_error() && !self.is_transient()
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bonusly returned {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Whether a request can be repeated without repeating its effect.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Idempotence {
+    /// Like a `GET`; retry any transient failure.
+    Idempotent,
+    /// Like sending a bonus; only retry failures where the request was never
+    /// processed.
+    NotIdempotent,
+}
+
+/// The outcome of one attempt at a request.
+enum Attempt<T> {
+    Done(eyre::Result<T>),
+    Retry {
+        error: Report,
+        /// From the `Retry-After` header.
+        after: Option<Duration>,
+    },
+}
+
+/// Parse a `Retry-After` header, which is either a number of seconds or an
+/// HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+impl ClientBuilder {
+    /// The API URL to send requests to, like `http://localhost:8080/api/v1`.
+    /// Defaults to Bonusly's.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// The HTTP client to send requests with, for timeouts, proxies, user
+    /// agents, etc.
+    pub fn http_client(mut self, client: HttpClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// How to retry failed requests. Defaults to [`RetryPolicy::default`].
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// The application name to send with every request. Defaults to
+    /// `cherries-4-prs`.
+    pub fn application_name(mut self, application_name: impl Into<String>) -> Self {
+        self.application_name = application_name.into();
+        self
+    }
+
+    pub fn build(self) -> Client {
+        Client {
+            token: self.token,
+            client: self.client.unwrap_or_default(),
+            base_url: self.base_url,
+            application_name: self.application_name,
+            retry_policy: self.retry_policy,
+        }
+    }
 }
 
 impl Client {
-    /// Construct a client from a token.
+    /// Construct a client from a token, with the default settings.
     pub fn from_token(token: String) -> Self {
-        Client {
+        Self::builder(token).build()
+    }
+
+    /// Construct a client from a token, with custom settings.
+    pub fn builder(token: String) -> ClientBuilder {
+        ClientBuilder {
             token: SecretString::from(token),
-            client: HttpClient::new(),
+            client: None,
+            base_url: BONUSLY_API_URL.to_owned(),
+            application_name: APPLICATION_NAME.to_owned(),
+            retry_policy: RetryPolicy::default(),
         }
     }
 
     fn request(&self, method: reqwest::Method, endpoint: impl AsRef<str>) -> RequestBuilder {
         self.client
-            .request(method, format!("{}{}", BONUSLY_API_URL, endpoint.as_ref()))
+            .request(method, format!("{}{}", self.base_url, endpoint.as_ref()))
             .bearer_auth(self.token.expose_secret())
-            .header("HTTP_APPLICATION_NAME", "cherries-4-prs")
+            .header("HTTP_APPLICATION_NAME", &self.application_name)
+    }
+
+    /// Send the request built by `request`, retrying according to the
+    /// client's [`RetryPolicy`].
+    async fn call<T: DeserializeOwned>(
+        &self,
+        idempotence: Idempotence,
+        request: impl Fn() -> RequestBuilder,
+    ) -> eyre::Result<T> {
+        let mut retry = 0;
+        loop {
+            match Self::attempt(idempotence, request()).await {
+                Attempt::Done(result) => return result,
+                Attempt::Retry { error, after } => {
+                    if retry >= self.retry_policy.max_retries {
+                        return Err(error);
+                    }
+                    let delay = after.unwrap_or_else(|| self.retry_policy.backoff(retry));
+                    retry += 1;
+                    warn!(retry, ?delay, "Bonusly request failed, retrying: {error}");
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    async fn attempt<T: DeserializeOwned>(
+        idempotence: Idempotence,
+        request: RequestBuilder,
+    ) -> Attempt<T> {
+        let idempotent = idempotence == Idempotence::Idempotent;
+        let response = match request.send().await {
+            Ok(response) => response,
+            // A timeout may come after the request was processed.
+            Err(err) if err.is_connect() || (idempotent && err.is_timeout()) => {
+                return Attempt::Retry {
+                    error: err.into(),
+                    after: None,
+                }
+            }
+            Err(err) => return Attempt::Done(Err(err.into())),
+        };
+        let status = response.status();
+        let after = retry_after(response.headers());
+        let body = match response.bytes().await {
+            Ok(body) => body,
+            Err(err) if idempotent || status == StatusCode::TOO_MANY_REQUESTS => {
+                return Attempt::Retry {
+                    error: err.into(),
+                    after: None,
+                }
+            }
+            Err(err) => {
+                return Attempt::Done(
+                    Err(err).wrap_err_with(|| format!("Failed to read {status} response body")),
+                )
+            }
+        };
+
+        let message = match serde_json::from_slice::<BonuslyResult<T>>(&body)
+            .map(BonuslyResult::into_result)
+        {
+            Ok(Ok(result)) if status.is_success() => return Attempt::Done(Ok(result)),
+            Ok(Err(message)) => message,
+            Err(err) if status.is_success() => {
+                return Attempt::Done(Err(err).wrap_err("Failed to parse response from Bonusly"))
+            }
+            // Not a Bonusly error; maybe from a proxy.
+            Ok(Ok(_)) | Err(_) => String::from_utf8_lossy(&body).into_owned(),
+        };
+        let error = ApiError { status, message };
+        let retry = if idempotent {
+            error.is_transient()
+        } else {
+            status == StatusCode::TOO_MANY_REQUESTS
+        };
+        if retry {
+            Attempt::Retry {
+                error: error.into(),
+                after,
+            }
+        } else {
+            Attempt::Done(Err(error.into()))
+        }
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -39,15 +303,12 @@ impl Client {
         let mut ret = Vec::with_capacity(1000);
 
         loop {
-            let mut users = self
-                .request(reqwest::Method::GET, "/users")
-                .query(&[("limit", LIMIT.to_string()), ("skip", skip.to_string())])
-                .send()
-                .await?
-                .json::<BonuslyResult<Vec<User>>>()
-                .await?
-                .into_result()
-                .map_err(Report::msg)?;
+            let mut users: Vec<User> = self
+                .call(Idempotence::Idempotent, || {
+                    self.request(reqwest::Method::GET, "/users")
+                        .query(&[("limit", LIMIT.to_string()), ("skip", skip.to_string())])
+                })
+                .await?;
             let done = users.len() < LIMIT;
             skip += users.len();
             ret.append(&mut users);
@@ -60,43 +321,73 @@ impl Client {
         Ok(ret)
     }
 
+    /// Bonuses given by `giver_email` since `since`.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn sent_bonuses(
+        &self,
+        giver_email: &str,
+        since: DateTime<Utc>,
+    ) -> eyre::Result<Vec<SentBonus>> {
+        const LIMIT: usize = 100;
+        let mut skip: usize = 0;
+        let mut ret = Vec::new();
+
+        loop {
+            let mut bonuses: Vec<SentBonus> = self
+                .call(Idempotence::Idempotent, || {
+                    self.request(reqwest::Method::GET, "/bonuses").query(&[
+                        ("limit", LIMIT.to_string()),
+                        ("skip", skip.to_string()),
+                        ("giver_email", giver_email.to_owned()),
+                        ("start_time", since.to_rfc3339()),
+                    ])
+                })
+                .await?;
+            let done = bonuses.len() < LIMIT;
+            skip += bonuses.len();
+            ret.append(&mut bonuses);
+            if done {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        }
+
+        Ok(ret)
+    }
+
     pub async fn my_email(&self) -> eyre::Result<String> {
         Ok(self.me().await?.email)
     }
 
     pub async fn me(&self) -> eyre::Result<User> {
-        self.request(reqwest::Method::GET, "/users/me")
-            .send()
-            .await?
-            .json::<BonuslyResult<User>>()
-            .await?
-            .into_result()
-            .map_err(Report::msg)
+        self.call(Idempotence::Idempotent, || {
+            self.request(reqwest::Method::GET, "/users/me")
+        })
+        .await
     }
 
     pub async fn company(&self) -> eyre::Result<Company> {
-        self.request(reqwest::Method::GET, "/companies/show")
-            .send()
-            .await?
-            .json::<BonuslyResult<Company>>()
-            .await?
-            .into_result()
-            .map_err(Report::msg)
+        self.call(Idempotence::Idempotent, || {
+            self.request(reqwest::Method::GET, "/companies/show")
+        })
+        .await
     }
 
     pub async fn hashtags(&self) -> eyre::Result<Vec<String>> {
         Ok(self.company().await?.company_hashtags)
     }
 
+    /// Send a bonus. Unless the error is an [`ApiError::is_rejection`], the
+    /// bonus may have been sent even if this fails.
     pub async fn send_bonus(&self, bonus: &Bonus) -> eyre::Result<BonusReply> {
-        self.request(reqwest::Method::POST, "/bonuses")
-            .json(bonus)
-            .send()
-            .await?
-            .json::<BonuslyResult<BonusReply>>()
-            .await?
-            .into_result()
-            .map_err(Report::msg)
+        self.call(Idempotence::NotIdempotent, || {
+            self.request(reqwest::Method::POST, "/bonuses").json(bonus)
+        })
+        .await
+        .map_err(|err| match err.downcast_ref::<ApiError>() {
+            Some(api_error) if api_error.is_rejection() => err,
+            _ => err.wrap_err("Bonus may or may not have been sent"),
+        })
     }
 }
 
@@ -135,6 +426,10 @@ pub struct User {
     pub last_name: String,
     pub email: String,
     pub can_receive: bool,
+    /// Cherries this user has left to give this month. Only reported for the
+    /// current user; see [`Client::me`].
+    #[serde(default)]
+    pub giving_balance: Option<usize>,
 }
 
 /// A company on Bonusly. Contains the list of hashtags.
@@ -143,7 +438,7 @@ pub struct Company {
     company_hashtags: Vec<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Bonus {
     pub receiver_email: String,
     pub amount: usize,
@@ -154,7 +449,39 @@ pub struct Bonus {
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BonusReply {
-    id: String,
-    created_at: String,
-    reason: String,
+    pub id: String,
+    pub created_at: String,
+    pub reason: String,
+}
+
+/// A bonus that has already been given; see [`Client::sent_bonuses`].
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SentBonus {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub reason: String,
+    /// Cherries given to each receiver.
+    pub amount: usize,
+    #[serde(default)]
+    pub receivers: Vec<Receiver>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Receiver {
+    pub email: String,
+}
+
+impl SentBonus {
+    /// Whether this is `bonus`: the same amount for the same receiver, for the
+    /// same reason, and not created before `not_before`. Bonusly may add to
+    /// the reason, e.g. the hashtag.
+    pub fn matches(&self, bonus: &Bonus, not_before: DateTime<Utc>) -> bool {
+        self.created_at >= not_before
+            && self.amount == bonus.amount
+            && self
+                .receivers
+                .iter()
+                .any(|receiver| receiver.email == bonus.receiver_email)
+            && self.reason.contains(&bonus.reason)
+    }
 }
@@ -0,0 +1,193 @@
+//! Tracking the monthly Bonusly allowance and spending it down before it
+//! expires.
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::bonusly;
+use crate::{Config, Program, ReviewSource, RewardSink, SendErrors};
+
+const SPEND_DOWN_REASON: &str = "thanks for all the code reviews this month!";
+
+/// How many approvals each receiver has been sent cherries for this month.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MonthlyApprovals {
+    /// The month these approvals are from, formatted like `2022-03`. Empty
+    /// if nothing has been sent yet.
+    pub month: String,
+    /// Map from Bonusly email to number of approvals.
+    pub approvals: BTreeMap<String, usize>,
+    /// Whether the remaining balance has already been spent down this month.
+    pub spent_down: bool,
+}
+
+impl MonthlyApprovals {
+    /// Forget approvals from previous months.
+    fn roll_over(&mut self, now: DateTime<Utc>) {
+        let month = month_key(now);
+        if self.month != month {
+            *self = Self {
+                month,
+                ..Default::default()
+            };
+        }
+    }
+
+    /// Record that `receiver_email` was sent cherries for an approval.
+    pub fn record(&mut self, receiver_email: &str, now: DateTime<Utc>) {
+        self.roll_over(now);
+        *self.approvals.entry(receiver_email.to_owned()).or_default() += 1;
+    }
+}
+
+fn month_key(datetime: DateTime<Utc>) -> String {
+    datetime.format("%Y-%m").to_string()
+}
+
+/// Number of days from `now` until the start of next month, rounded up.
+fn days_until_month_end(now: DateTime<Utc>) -> i64 {
+    let (year, month) = if now.month() == 12 {
+        (now.year() + 1, 1)
+    } else {
+        (now.year(), now.month() + 1)
+    };
+    let next_month = Utc.ymd(year, month, 1).and_hms(0, 0, 0);
+    let remaining = next_month - now;
+    (remaining.num_seconds() + 86_399) / 86_400
+}
+
+/// Split `balance` between receivers proportionally to their number of
+/// approvals, using the largest remainder method. Receivers whose share
+/// rounds to zero are omitted.
+pub fn proportional_shares(
+    balance: usize,
+    approvals: &BTreeMap<String, usize>,
+) -> Vec<(String, usize)> {
+    let total: usize = approvals.values().sum();
+    if total == 0 || balance == 0 {
+        return Vec::new();
+    }
+
+    let mut shares: Vec<(String, usize, usize)> = approvals
+        .iter()
+        .map(|(email, count)| {
+            let exact = balance * count;
+            (email.clone(), exact / total, exact % total)
+        })
+        .collect();
+    let mut leftover = balance - shares.iter().map(|(_, share, _)| share).sum::<usize>();
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by(|&a, &b| shares[b].2.cmp(&shares[a].2));
+    for i in by_remainder {
+        if leftover == 0 {
+            break;
+        }
+        shares[i].1 += 1;
+        leftover -= 1;
+    }
+
+    shares
+        .into_iter()
+        .filter(|(_, share, _)| *share > 0)
+        .map(|(email, share, _)| (email, share))
+        .collect()
+}
+
+impl<S: ReviewSource, R: RewardSink> Program<S, R> {
+    /// Cherries `author` has left to give this month, if known.
+    pub(crate) fn giving_balance(&self, author: &str) -> Option<usize> {
+        self.giving_balances.get(author).copied().flatten()
+    }
+
+    /// Subtract `amount` from `author`'s remaining balance.
+    pub(crate) fn spend(&mut self, author: &str, amount: usize) {
+        if let Some(Some(balance)) = self.giving_balances.get_mut(author) {
+            *balance = balance.saturating_sub(amount);
+        }
+    }
+
+    /// Fetch the author's remaining monthly giving balance from Bonusly.
+    #[instrument(skip_all, fields(author = %config.github.user), level = "debug")]
+    pub(crate) async fn refresh_giving_balance(&mut self, config: &Config) -> eyre::Result<()> {
+        let author = &config.github.user;
+        let me = self
+            .credentials
+            .bonusly_for(&self.config, author)?
+            .me()
+            .await?;
+        match me.giving_balance {
+            Some(balance) => debug!(balance, "Fetched Bonusly giving balance"),
+            None => warn!("Bonusly didn't report a giving balance; not enforcing a budget"),
+        }
+        self.giving_balances
+            .insert(author.clone(), me.giving_balance);
+        Ok(())
+    }
+
+    /// If spending down is enabled and the month is almost over, send the
+    /// rest of the author's giving balance to this month's reviewers,
+    /// proportionally to how many PRs each approved.
+    #[instrument(skip_all, fields(author = %config.github.user), level = "debug")]
+    pub(crate) async fn maybe_spend_down(&mut self, config: &Config) -> eyre::Result<()> {
+        let days = match config.spend_down_days {
+            Some(days) => days,
+            None => return Ok(()),
+        };
+        let author = &config.github.user;
+        let now = Utc::now();
+        let monthly_approvals = &mut self.state.author_mut(author).monthly_approvals;
+        monthly_approvals.roll_over(now);
+        if monthly_approvals.spent_down || days_until_month_end(now) > days {
+            return Ok(());
+        }
+        let balance = match self.giving_balance(author) {
+            Some(balance) if balance > 0 => balance,
+            _ => return Ok(()),
+        };
+        let shares = proportional_shares(
+            balance,
+            &self.state.author(author).monthly_approvals.approvals,
+        );
+        if shares.is_empty() {
+            return Ok(());
+        }
+        info!(balance, ?shares, "Spending down remaining balance");
+        if !self.dry_run {
+            // Don't try again this month, even if the program dies or some
+            // bonuses fail; better to leave cherries on the table than to
+            // send them twice.
+            self.state.author_mut(author).monthly_approvals.spent_down = true;
+            self.write_state().await?;
+        }
+
+        let mut errors = Vec::new();
+        for (receiver_email, amount) in shares {
+            let hashtag = config.hashtags.choose(
+                Default::default(),
+                &self.state.hashtags,
+                &mut self.state.hashtag_round_robin,
+            )?;
+            let bonus = bonusly::Bonus {
+                receiver_email,
+                amount,
+                hashtag,
+                reason: SPEND_DOWN_REASON.to_owned(),
+            };
+            let interval = config.send_bonus_interval.max(Duration::from_secs(10));
+            if let Err(err) = self.send_bonus(author, Vec::new(), &bonus, interval).await {
+                error!("Error while sending spend-down cherries: {:?}", err);
+                errors.push(err);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SendErrors(errors).into())
+        }
+    }
+}
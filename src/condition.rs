@@ -0,0 +1,38 @@
+//! Which PRs an `[amount]`, `[reasons]`, or `[hashtags]` rule applies to.
+use serde::Deserialize;
+
+use crate::github;
+
+/// The PR conditions shared by every kind of rule. A rule matches PRs that
+/// meet all of its conditions.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Condition {
+    /// Repository name, or `owner/repo`.
+    pub repo: Option<String>,
+    /// The PR has this label. Requires fetching PR details.
+    pub label: Option<String>,
+}
+
+impl Condition {
+    /// Whether checking the condition needs PR details from GitHub.
+    pub fn needs_details(&self) -> bool {
+        self.label.is_some()
+    }
+
+    /// Whether `pr` meets the condition. Without `details`, a `label`
+    /// condition never matches.
+    pub fn matches(
+        &self,
+        pr: &github::PullRequest,
+        details: Option<&github::PullRequestDetails>,
+    ) -> bool {
+        let github::PullRequest { org, repo, .. } = pr;
+        self.repo
+            .as_ref()
+            .is_none_or(|want| want == repo || *want == format!("{org}/{repo}"))
+            && self.label.as_ref().is_none_or(|want| {
+                details
+                    .is_some_and(|details| details.labels.iter().any(|label| &label.name == want))
+            })
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -6,10 +7,15 @@ use color_eyre::eyre;
 use color_eyre::eyre::WrapErr;
 use tracing::info;
 use tracing::instrument;
+use tracing::warn;
 
+use crate::amount;
 use crate::api;
 use crate::bonusly;
 use crate::github;
+use crate::hashtag;
+use crate::matching::{best_match, rank_bonusly_users, BonuslyMatch, Candidate};
+use crate::reason;
 
 const SECONDS_PER_MINUTE: u64 = 60;
 
@@ -20,11 +26,25 @@ pub struct Config {
     pub path: PathBuf,
     pub github: github::Config,
     pub cherries_per_check: usize,
+    /// Rules for how many cherries to send; `base` is always set.
+    pub amount: amount::Config,
+    /// Templates for the reason sent with each bonus.
+    pub reasons: reason::Config,
+    /// How to choose the hashtag sent with each bonus.
+    pub hashtags: hashtag::Config,
     pub state_path: PathBuf,
     pub credentials_path: PathBuf,
     pub pr_check_interval: Duration,
     pub state_update_interval: chrono::Duration,
     pub send_bonus_interval: Duration,
+    /// Spend down the remaining balance this many days before the end of the
+    /// month, if set.
+    pub spend_down_days: Option<i64>,
+    /// Send one bonus per receiver per check, rather than one per review.
+    /// Combined bonuses don't use `reasons`; see [`batch_reviews`](crate::batch_reviews).
+    pub batch_bonuses: bool,
+    /// Authors besides `github.user`; see [`Config::author_configs`].
+    pub authors: Vec<api::Author>,
 }
 
 impl Config {
@@ -43,47 +63,158 @@ impl Config {
                 .with_context(|| format!("Failed to read config from {path:?}"))?,
         )?;
 
+        let mut amount = config.amount;
+        amount.base.get_or_insert(config.cherries_per_check);
+        config.github.validate()?;
+        amount.validate()?;
+        config.reasons.validate()?;
+        for author in &config.authors {
+            if let Some(reasons) = &author.reasons {
+                reasons.validate()?;
+            }
+            if author.user == config.github.user {
+                return Err(eyre::eyre!(
+                    "Author {} is already `github.user`; remove it from `authors`",
+                    author.user
+                ));
+            }
+        }
+
         Ok(Self {
             path,
             github: config.github,
             cherries_per_check: config.cherries_per_check,
+            amount,
+            reasons: config.reasons,
+            hashtags: config.hashtags,
             state_path: config_parent.join(config.data_path),
             credentials_path: config_parent.join(config.credentials_path),
             pr_check_interval: Duration::from_secs(config.pr_check_minutes * SECONDS_PER_MINUTE),
             state_update_interval: chrono::Duration::days(config.state_update_days),
             send_bonus_interval: Duration::from_secs(config.send_bonus_delay_seconds),
+            spend_down_days: config.spend_down_days,
+            batch_bonuses: config.batch_bonuses,
+            authors: config.authors,
         })
     }
 
+    /// The config for each author, starting with `github.user`. Additional
+    /// authors' configs have their overrides applied, `github.user` set to
+    /// the author, and no `authors`.
+    pub fn author_configs(&self) -> Vec<Config> {
+        let mut main = self.clone();
+        main.authors.clear();
+        let mut ret = vec![main.clone()];
+        for author in &self.authors {
+            let mut config = main.clone();
+            config.github.user = author.user.clone();
+            if let Some(cherries_per_check) = author.cherries_per_check {
+                config.cherries_per_check = cherries_per_check;
+                config.amount.base = Some(cherries_per_check);
+            }
+            if let Some(spend_down_days) = author.spend_down_days {
+                config.spend_down_days = Some(spend_down_days);
+            }
+            if let Some(batch_bonuses) = author.batch_bonuses {
+                config.batch_bonuses = batch_bonuses;
+            }
+            if let Some(reasons) = &author.reasons {
+                config.reasons = reasons.clone();
+            }
+            if let Some(hashtags) = &author.hashtags {
+                config.hashtags = hashtags.clone();
+            }
+            ret.push(config);
+        }
+        ret
+    }
+
     /// Find the bonusly email for a given GitHub user.
+    ///
+    /// Exact matches are used as-is; otherwise every Bonusly user is ranked
+    /// by how closely their name matches and the best is accepted only if it
+    /// beats `github.match_threshold`. Either way, the email must belong to a
+    /// Bonusly user who can receive bonuses; users who can't are skipped in
+    /// favor of the next match.
+    ///
+    /// `learned` maps GitHub usernames to emails chosen with the `resolve`
+    /// command; the config's `github.emails` take precedence over it.
     pub fn find_bonusly_email(
         &self,
         users: &[bonusly::User],
+        learned: &HashMap<String, String>,
+        find: &github::User,
+    ) -> BonuslyMatch {
+        let can_receive = |email: &str| {
+            users
+                .iter()
+                .any(|user| user.can_receive && user.email.eq_ignore_ascii_case(email))
+        };
+        match self.match_bonusly_email(users, learned, find, can_receive) {
+            BonuslyMatch::Found(candidate) => {
+                if can_receive(&candidate.email) {
+                    BonuslyMatch::Found(candidate)
+                } else {
+                    warn!(
+                        login = %find.login,
+                        email = %candidate.email,
+                        "Matched email doesn't belong to a Bonusly user who can receive bonuses"
+                    );
+                    BonuslyMatch::Unresolved(vec![candidate])
+                }
+            }
+            unresolved @ BonuslyMatch::Unresolved(_) => unresolved,
+        }
+    }
+
+    fn match_bonusly_email(
+        &self,
+        users: &[bonusly::User],
+        learned: &HashMap<String, String>,
         find: &github::User,
-    ) -> Option<String> {
+        can_receive: impl Fn(&str) -> bool,
+    ) -> BonuslyMatch {
         // First check for overrides.
-        if let Some(email) = self.github.emails.get(&find.login) {
-            return Some(email.clone());
+        if let Some(email) = self
+            .github
+            .emails
+            .get(&find.login)
+            .or_else(|| learned.get(&find.login))
+        {
+            return BonuslyMatch::Found(Candidate::certain(
+                email.clone(),
+                find.name.clone().unwrap_or_default(),
+            ));
         }
 
-        // If find's GitHub profile lists an `@starry.com` email, use it.
+        // If find's GitHub profile lists an email in one of our domains, use
+        // it.
         if let Some(email) = &find.email {
-            if email.ends_with("@starry.com") {
-                return Some(email.clone());
+            if self.github.is_company_email(email) {
+                return BonuslyMatch::Found(Candidate::certain(
+                    email.clone(),
+                    find.name.clone().unwrap_or_default(),
+                ));
             }
         }
         // Otherwise, use full names / display names.
-        for user in users {
+        for user in users.iter().filter(|user| user.can_receive) {
             if let Some(name) = &find.name {
                 // N.b.: no bonusly users in the current data have
                 // `full_name != display_name`.
                 if &user.full_name == name || &user.display_name == name {
-                    return Some(user.email.clone());
+                    return BonuslyMatch::Found(Candidate::certain(
+                        user.email.clone(),
+                        user.full_name.clone(),
+                    ));
                 }
 
                 // Try a prefix match, for e.g. "Justin Wood (Callek)"
                 if !user.full_name.is_empty() && name.starts_with(&user.full_name) {
-                    return Some(user.email.clone());
+                    return BonuslyMatch::Found(Candidate::certain(
+                        user.email.clone(),
+                        user.full_name.clone(),
+                    ));
                 }
 
                 // Try replacing e.g. "Matthew" with "Matt" to see if we get a
@@ -91,11 +222,20 @@ impl Config {
                 for (needle, haystack) in NAME_REPLACEMENTS {
                     let replaced = user.full_name.replace(needle, haystack);
                     if &replaced == name {
-                        return Some(user.email.clone());
+                        return BonuslyMatch::Found(Candidate::certain(
+                            user.email.clone(),
+                            user.full_name.clone(),
+                        ));
                     }
                 }
             }
         }
-        None
+
+        // Finally, fall back to fuzzy matching.
+        let candidates = rank_bonusly_users(users, find)
+            .into_iter()
+            .filter(|candidate| can_receive(&candidate.email))
+            .collect();
+        best_match(candidates, self.github.match_threshold)
     }
 }
@@ -1,11 +1,48 @@
-use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+
+use color_eyre::eyre;
+use color_eyre::eyre::WrapErr;
+use tracing::info;
 
 use crate::api;
 use crate::bonusly;
+use crate::github;
+use crate::Config;
+
+/// Clients for the GitHub and Bonusly APIs; see [`crate::ReviewSource`] and
+/// [`crate::RewardSink`].
+pub struct Credentials<S = github::Client, R = bonusly::Client> {
+    /// Bonusly client for the main author, `github.user`.
+    pub bonusly: R,
+    pub github: S,
+    /// Bonusly clients for additional authors, by GitHub username.
+    pub authors: HashMap<String, R>,
+}
+
+impl Credentials {
+    /// Read the credentials file named in `config`.
+    pub fn from_config(config: &Config) -> eyre::Result<Self> {
+        let credentials_path = &config.credentials_path;
+        info!(?credentials_path, "Reading credentials");
+        let raw: api::Credentials =
+            toml::de::from_str(&fs::read_to_string(credentials_path).with_context(|| {
+                format!("Failed to read credentials from {credentials_path:?}")
+            })?)?;
+        Self::try_from((raw, &config.github))
+    }
+}
 
-#[derive(Deserialize)]
-#[serde(try_from = "api::Credentials")]
-pub struct Credentials {
-    pub bonusly: bonusly::Client,
-    pub github: octocrab::Octocrab,
+impl<S, R> Credentials<S, R> {
+    /// The Bonusly client to send `author`'s cherries with.
+    pub fn bonusly_for(&self, config: &Config, author: &str) -> eyre::Result<&R> {
+        if author == config.github.user {
+            Ok(&self.bonusly)
+        } else {
+            self.authors.get(author).ok_or_else(|| {
+                eyre::eyre!("No Bonusly token for author {author} in the credentials file")
+            })
+        }
+    }
 }
@@ -0,0 +1,350 @@
+//! In-memory implementations of [`ReviewSource`] and [`RewardSink`], for
+//! testing [`Program`](crate::Program) without network access.
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre;
+
+use crate::bonusly;
+use crate::github;
+use crate::{ReviewSource, RewardSink};
+
+/// A fake GitHub holding pull requests, reviews, and users added by the
+/// test.
+#[derive(Default)]
+pub struct FakeGitHub {
+    data: Mutex<GitHubData>,
+}
+
+#[derive(Default)]
+struct GitHubData {
+    prs: Vec<github::PullRequest>,
+    reviews: HashMap<github::PullRequest, Vec<github::Review>>,
+    details: HashMap<github::PullRequest, github::PullRequestDetails>,
+    comments: HashMap<github::PullRequest, Vec<github::ReviewComment>>,
+    users: HashMap<String, github::User>,
+    next_id: u64,
+}
+
+impl FakeGitHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a user, who can then review pull requests.
+    pub fn add_user(&self, login: &str, name: Option<&str>, email: Option<&str>) {
+        let mut data = self.data.lock().unwrap();
+        data.next_id += 1;
+        let user = github::User {
+            id: data.next_id,
+            login: login.to_owned(),
+            name: name.map(str::to_owned),
+            email: email.map(str::to_owned),
+        };
+        data.users.insert(login.to_owned(), user);
+    }
+
+    /// Add a pull request with no reviews, if it doesn't already exist.
+    pub fn add_pr(&self, pr: &github::PullRequest) {
+        let mut data = self.data.lock().unwrap();
+        if !data.prs.contains(pr) {
+            data.prs.push(pr.clone());
+        }
+    }
+
+    /// Set the details of a pull request; see
+    /// [`ReviewSource::pr_details`].
+    pub fn set_details(&self, pr: &github::PullRequest, details: github::PullRequestDetails) {
+        self.add_pr(pr);
+        self.data
+            .lock()
+            .unwrap()
+            .details
+            .insert(pr.clone(), details);
+    }
+
+    /// Add a review in `state` from `reviewer`, adding the pull request if
+    /// needed.
+    pub fn add_review(
+        &self,
+        pr: &github::PullRequest,
+        reviewer: &str,
+        state: github::ReviewState,
+    ) -> github::ReviewId {
+        self.add_pr(pr);
+        let mut data = self.data.lock().unwrap();
+        data.next_id += 1;
+        let id = github::ReviewId(data.next_id);
+        let github::PullRequest { org, repo, number } = pr;
+        let review = github::Review {
+            id,
+            user: github::UserLogin {
+                login: reviewer.to_owned(),
+            },
+            state: Some(state),
+            html_url: format!(
+                "https://github.com/{org}/{repo}/pull/{number}#pullrequestreview-{id}"
+            ),
+            submitted_at: Some(Utc::now()),
+        };
+        data.reviews.entry(pr.clone()).or_default().push(review);
+        id
+    }
+
+    /// Approve a pull request as `reviewer`.
+    pub fn approve(&self, pr: &github::PullRequest, reviewer: &str) -> github::ReviewId {
+        self.add_review(pr, reviewer, github::ReviewState::Approved)
+    }
+
+    /// Leave a review comment on a pull request as `reviewer`.
+    pub fn add_review_comment(&self, pr: &github::PullRequest, reviewer: &str) {
+        self.add_pr(pr);
+        self.data
+            .lock()
+            .unwrap()
+            .comments
+            .entry(pr.clone())
+            .or_default()
+            .push(github::ReviewComment {
+                user: github::UserLogin {
+                    login: reviewer.to_owned(),
+                },
+            });
+    }
+}
+
+impl ReviewSource for FakeGitHub {
+    /// Pull requests with an approved review, in the `org:` and `repo:`
+    /// scopes in the query. Other qualifiers are ignored.
+    async fn search_prs(&self, query: &str) -> eyre::Result<Vec<github::PullRequest>> {
+        let data = self.data.lock().unwrap();
+        let mut orgs = Vec::new();
+        let mut repos = Vec::new();
+        for term in query.split_whitespace() {
+            if let Some(org) = term.strip_prefix("org:") {
+                orgs.push(org);
+            } else if let Some(repo) = term.strip_prefix("repo:") {
+                repos.push(repo);
+            }
+        }
+        Ok(data
+            .prs
+            .iter()
+            .filter(|pr| {
+                (orgs.is_empty() && repos.is_empty())
+                    || orgs.contains(&pr.org.as_str())
+                    || repos.contains(&format!("{}/{}", pr.org, pr.repo).as_str())
+            })
+            .filter(|pr| {
+                data.reviews.get(*pr).is_some_and(|reviews| {
+                    reviews
+                        .iter()
+                        .any(|review| review.state == Some(github::ReviewState::Approved))
+                })
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn list_reviews(&self, pr: &github::PullRequest) -> eyre::Result<Vec<github::Review>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.reviews.get(pr).cloned().unwrap_or_default())
+    }
+
+    async fn review(
+        &self,
+        pr: &github::PullRequest,
+        id: github::ReviewId,
+    ) -> eyre::Result<github::Review> {
+        let data = self.data.lock().unwrap();
+        data.reviews
+            .get(pr)
+            .and_then(|reviews| reviews.iter().find(|review| review.id == id))
+            .cloned()
+            .ok_or_else(|| eyre::eyre!("No review {id} on {pr:?}"))
+    }
+
+    async fn user(&self, login: &str) -> eyre::Result<github::User> {
+        let data = self.data.lock().unwrap();
+        data.users
+            .get(login)
+            .cloned()
+            .ok_or_else(|| eyre::eyre!("No GitHub user {login}"))
+    }
+
+    async fn pr_details(
+        &self,
+        pr: &github::PullRequest,
+    ) -> eyre::Result<github::PullRequestDetails> {
+        let data = self.data.lock().unwrap();
+        let github::PullRequest { org, repo, number } = pr;
+        Ok(data
+            .details
+            .get(pr)
+            .cloned()
+            .unwrap_or_else(|| github::PullRequestDetails {
+                title: format!("{org}/{repo}#{number}"),
+                html_url: format!("https://github.com/{org}/{repo}/pull/{number}"),
+                additions: 0,
+                deletions: 0,
+                changed_files: 0,
+                labels: Vec::new(),
+            }))
+    }
+
+    async fn review_comments(
+        &self,
+        pr: &github::PullRequest,
+    ) -> eyre::Result<Vec<github::ReviewComment>> {
+        let data = self.data.lock().unwrap();
+        Ok(data.comments.get(pr).cloned().unwrap_or_default())
+    }
+}
+
+/// A fake Bonusly company, which records the bonuses sent to it.
+pub struct FakeBonusly {
+    data: Mutex<BonuslyData>,
+}
+
+struct BonuslyData {
+    me: bonusly::User,
+    users: Vec<bonusly::User>,
+    hashtags: Vec<String>,
+    /// Bonuses sent so far, and when.
+    sent: Vec<(bonusly::Bonus, DateTime<Utc>)>,
+}
+
+/// A Bonusly user who can receive bonuses.
+pub fn bonusly_user(email: &str, full_name: &str) -> bonusly::User {
+    let (first_name, last_name) = full_name.split_once(' ').unwrap_or((full_name, ""));
+    let short_name = email.split('@').next().unwrap_or(email);
+    bonusly::User {
+        id: email.to_owned(),
+        short_name: short_name.to_owned(),
+        full_name: full_name.to_owned(),
+        display_name: full_name.to_owned(),
+        first_name: first_name.to_owned(),
+        last_name: last_name.to_owned(),
+        email: email.to_owned(),
+        can_receive: true,
+        giving_balance: None,
+    }
+}
+
+impl FakeBonusly {
+    /// A company with one user, `my_email`, who sends the bonuses. There's
+    /// no giving balance and a single hashtag, `#teamwork`.
+    pub fn new(my_email: &str) -> Self {
+        let me = bonusly_user(my_email, "Me");
+        Self {
+            data: Mutex::new(BonuslyData {
+                users: vec![me.clone()],
+                me,
+                hashtags: vec!["#teamwork".to_owned()],
+                sent: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn add_user(&self, email: &str, full_name: &str) {
+        self.data
+            .lock()
+            .unwrap()
+            .users
+            .push(bonusly_user(email, full_name));
+    }
+
+    pub fn set_hashtags(&self, hashtags: &[&str]) {
+        self.data.lock().unwrap().hashtags = hashtags.iter().map(|s| (*s).to_owned()).collect();
+    }
+
+    /// Limit the cherries that can be sent, or `None` for no limit.
+    pub fn set_giving_balance(&self, balance: Option<usize>) {
+        self.data.lock().unwrap().me.giving_balance = balance;
+    }
+
+    /// Bonuses sent so far, oldest first.
+    pub fn sent(&self) -> Vec<bonusly::Bonus> {
+        self.data
+            .lock()
+            .unwrap()
+            .sent
+            .iter()
+            .map(|(bonus, _)| bonus.clone())
+            .collect()
+    }
+}
+
+impl RewardSink for FakeBonusly {
+    async fn list_users(&self) -> eyre::Result<Vec<bonusly::User>> {
+        Ok(self.data.lock().unwrap().users.clone())
+    }
+
+    async fn hashtags(&self) -> eyre::Result<Vec<String>> {
+        Ok(self.data.lock().unwrap().hashtags.clone())
+    }
+
+    async fn me(&self) -> eyre::Result<bonusly::User> {
+        Ok(self.data.lock().unwrap().me.clone())
+    }
+
+    /// Records the bonus, failing like Bonusly does if the receiver doesn't
+    /// exist, the hashtag isn't the company's, or the balance is too low.
+    async fn send_bonus(&self, bonus: &bonusly::Bonus) -> eyre::Result<bonusly::BonusReply> {
+        let mut data = self.data.lock().unwrap();
+        if !data
+            .users
+            .iter()
+            .any(|user| user.can_receive && user.email == bonus.receiver_email)
+        {
+            return Err(rejected(format!(
+                "No Bonusly user {}",
+                bonus.receiver_email
+            )));
+        }
+        if !data.hashtags.contains(&bonus.hashtag) {
+            return Err(rejected(format!("Unknown hashtag {}", bonus.hashtag)));
+        }
+        if let Some(balance) = &mut data.me.giving_balance {
+            if bonus.amount > *balance {
+                return Err(rejected("Not enough cherries left to give".to_owned()));
+            }
+            *balance -= bonus.amount;
+        }
+        data.sent.push((bonus.clone(), Utc::now()));
+        Ok(bonusly::BonusReply {
+            id: data.sent.len().to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            reason: bonus.reason.clone(),
+        })
+    }
+
+    async fn sent_bonuses(&self, since: DateTime<Utc>) -> eyre::Result<Vec<bonusly::SentBonus>> {
+        let data = self.data.lock().unwrap();
+        Ok(data
+            .sent
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, sent_at))| *sent_at >= since)
+            .map(|(index, (bonus, sent_at))| bonusly::SentBonus {
+                id: (index + 1).to_string(),
+                created_at: *sent_at,
+                reason: bonus.reason.clone(),
+                amount: bonus.amount,
+                receivers: vec![bonusly::Receiver {
+                    email: bonus.receiver_email.clone(),
+                }],
+            })
+            .collect())
+    }
+}
+
+/// An error like Bonusly's for a bonus it won't send.
+fn rejected(message: String) -> eyre::Report {
+    bonusly::ApiError {
+        status: reqwest::StatusCode::UNPROCESSABLE_ENTITY,
+        message,
+    }
+    .into()
+}
@@ -1,15 +1,202 @@
 use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use chrono::{DateTime, Utc};
-use octocrab::Octocrab;
+use chrono::{DateTime, TimeZone, Utc};
+use color_eyre::eyre;
+use octocrab::{FromResponse, GitHubError, Octocrab};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::ReviewSource;
 
 pub use octocrab::models::issues::Issue;
-pub use octocrab::models::pulls::Review;
 pub use octocrab::models::pulls::ReviewState;
 pub use octocrab::models::ReviewId;
 pub use octocrab::Page;
 
+/// A GitHub client that keeps track of the API's rate limits, waiting for
+/// them to reset rather than running out.
+pub struct Client {
+    octocrab: Octocrab,
+    /// The latest rate limit seen for each resource; see [`resource`].
+    rate_limits: Mutex<HashMap<&'static str, RateLimit>>,
+}
+
+/// A rate limit window, from the `X-RateLimit-*` headers.
+#[derive(Clone, Copy, Debug)]
+struct RateLimit {
+    limit: u64,
+    remaining: u64,
+    reset: DateTime<Utc>,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+        Some(Self {
+            limit: header("x-ratelimit-limit")?,
+            remaining: header("x-ratelimit-remaining")?,
+            reset: Utc
+                .timestamp_opt(header("x-ratelimit-reset")? as i64, 0)
+                .single()?,
+        })
+    }
+
+    /// Whether to wait for the window to reset before making another
+    /// request. Leaves 2% of the limit for other uses of the token.
+    fn is_nearly_exhausted(&self) -> bool {
+        self.remaining <= self.limit / 50
+    }
+}
+
+/// The rate limit a request to `url` counts against. The search API has its
+/// own, much lower, limit.
+fn resource(url: &Url) -> &'static str {
+    if url.path().contains("/search/") {
+        "search"
+    } else {
+        "core"
+    }
+}
+
+/// GitHub refused a request because of rate limiting.
+#[derive(Debug)]
+pub enum RateLimited {
+    /// The request budget for `resource` is used up until `reset`.
+    Primary {
+        resource: &'static str,
+        reset: DateTime<Utc>,
+    },
+    /// Too many requests too quickly, regardless of the remaining budget.
+    /// See <https://docs.github.com/en/rest/overview/resources-in-the-rest-api#secondary-rate-limits>.
+    Secondary {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+}
+
+impl Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimited::Primary { resource, reset } => {
+                write!(f, "GitHub {resource} rate limit exhausted until {reset}")
+            }
+            RateLimited::Secondary {
+                message,
+                retry_after,
+            } => {
+                write!(f, "GitHub secondary rate limit hit: {message}")?;
+                if let Some(retry_after) = retry_after {
+                    write!(f, " (retry after {}s)", retry_after.as_secs())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+impl Client {
+    pub fn new(octocrab: Octocrab) -> Self {
+        Self {
+            octocrab,
+            rate_limits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Send a `GET` request to `route`, which may be a full URL, waiting
+    /// first if its rate limit is nearly exhausted.
+    pub async fn get<R: FromResponse, P: Serialize + ?Sized>(
+        &self,
+        route: impl AsRef<str>,
+        parameters: Option<&P>,
+    ) -> eyre::Result<R> {
+        let url = self.octocrab.absolute_url(route)?;
+        let resource = resource(&url);
+        self.wait_for_rate_limit(resource).await;
+
+        let response = self.octocrab._get(url, parameters).await?;
+        let rate_limit = RateLimit::from_headers(response.headers());
+        if let Some(rate_limit) = rate_limit {
+            debug!(
+                resource,
+                remaining = rate_limit.remaining,
+                limit = rate_limit.limit,
+                reset = %rate_limit.reset,
+                "GitHub rate limit"
+            );
+            self.rate_limits
+                .lock()
+                .unwrap()
+                .insert(resource, rate_limit);
+        }
+
+        let status = response.status();
+        if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+            if let Some(rate_limit) = rate_limit.filter(|rate_limit| rate_limit.remaining == 0) {
+                return Err(RateLimited::Primary {
+                    resource,
+                    reset: rate_limit.reset,
+                }
+                .into());
+            }
+            let retry_after = retry_after(response.headers());
+            let body = response.text().await?;
+            let message = serde_json::from_str::<GitHubError>(&body)
+                .map(|error| error.message)
+                .unwrap_or(body);
+            if retry_after.is_some() || message.to_lowercase().contains("secondary rate limit") {
+                return Err(RateLimited::Secondary {
+                    message,
+                    retry_after,
+                }
+                .into());
+            }
+            return Err(eyre::eyre!("GitHub returned {status}: {message}"));
+        }
+
+        let response = octocrab::map_github_error(response).await?;
+        Ok(R::from_response(response).await?)
+    }
+
+    /// Wait for `resource`'s rate limit to reset, if it's nearly exhausted.
+    async fn wait_for_rate_limit(&self, resource: &'static str) {
+        let rate_limit = self.rate_limits.lock().unwrap().get(resource).copied();
+        if let Some(rate_limit) = rate_limit.filter(RateLimit::is_nearly_exhausted) {
+            if let Ok(wait) = (rate_limit.reset - Utc::now()).to_std() {
+                info!(
+                    resource,
+                    remaining = rate_limit.remaining,
+                    reset = %rate_limit.reset,
+                    "GitHub rate limit nearly exhausted, waiting for it to reset"
+                );
+                // The reset time only has a resolution of seconds.
+                tokio::time::sleep(wait + Duration::from_secs(1)).await;
+            }
+            self.rate_limits.lock().unwrap().remove(resource);
+        }
+    }
+}
+
+/// Parse a `Retry-After` header in seconds, which is the only form GitHub
+/// sends.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct User {
     pub id: u64,
@@ -19,42 +206,317 @@ pub struct User {
 }
 
 impl User {
-    pub async fn from_login(github: &Octocrab, login: &str) -> Result<Self, octocrab::Error> {
+    pub async fn from_login(github: &Client, login: &str) -> eyre::Result<Self> {
         github.get(format!("users/{}", login), None::<&()>).await
     }
 }
 
+/// Details of a pull request not included in search results.
+#[derive(Clone, Deserialize, Debug)]
+pub struct PullRequestDetails {
+    pub title: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub additions: u64,
+    #[serde(default)]
+    pub deletions: u64,
+    #[serde(default)]
+    pub changed_files: u64,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct Label {
+    pub name: String,
+}
+
+/// A review on a pull request.
+#[derive(Clone, Deserialize, Debug)]
+pub struct Review {
+    pub id: ReviewId,
+    pub user: UserLogin,
+    pub state: Option<ReviewState>,
+    pub html_url: String,
+    pub submitted_at: Option<DateTime<Utc>>,
+}
+
+/// A review comment on a pull request.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ReviewComment {
+    pub user: UserLogin,
+}
+
+/// The user who left a review or comment.
+#[derive(Clone, Deserialize, Debug)]
+pub struct UserLogin {
+    pub login: String,
+}
+
+impl PullRequestDetails {
+    pub async fn fetch(github: &Client, pr: &PullRequest) -> eyre::Result<Self> {
+        let PullRequest { org, repo, number } = pr;
+        github
+            .get(format!("repos/{org}/{repo}/pulls/{number}"), None::<&()>)
+            .await
+    }
+
+    pub fn lines_changed(&self) -> u64 {
+        self.additions + self.deletions
+    }
+}
+
+/// Every item from `first` and the pages after it, following `next` links.
+pub async fn all_pages<T: DeserializeOwned>(
+    github: &Client,
+    first: Page<T>,
+) -> eyre::Result<Vec<T>> {
+    let mut items = first.items;
+    let mut next = first.next;
+    while let Some(url) = next {
+        let page: Page<T> = github.get(url, None::<&()>).await?;
+        items.extend(page.items);
+        next = page.next;
+    }
+    Ok(items)
+}
+
+/// Every review on a pull request.
+pub async fn list_reviews(github: &Client, pr: &PullRequest) -> eyre::Result<Vec<Review>> {
+    let PullRequest { org, repo, number } = pr;
+    let first = github
+        .get(
+            format!("repos/{org}/{repo}/pulls/{number}/reviews"),
+            Some(&[("per_page", "100")]),
+        )
+        .await?;
+    all_pages(github, first).await
+}
+
+/// A single review on a pull request.
+pub async fn review(github: &Client, pr: &PullRequest, id: ReviewId) -> eyre::Result<Review> {
+    let PullRequest { org, repo, number } = pr;
+    github
+        .get(
+            format!("repos/{org}/{repo}/pulls/{number}/reviews/{id}"),
+            None::<&()>,
+        )
+        .await
+}
+
+/// Pull requests matching a search query, from every page of results.
+pub async fn search_prs(github: &Client, query: &str) -> eyre::Result<Vec<PullRequest>> {
+    let first: Page<Issue> = github
+        .get("search/issues", Some(&[("q", query), ("per_page", "100")]))
+        .await?;
+    all_pages(github, first)
+        .await?
+        .iter()
+        .map(|pr| {
+            let (org, repo) = org_repo(pr).ok_or_else(|| {
+                eyre::eyre!("Couldn't parse org/repo from url {}", &pr.repository_url)
+            })?;
+            Ok(PullRequest {
+                org: org.to_owned(),
+                repo: repo.to_owned(),
+                number: pr.number,
+            })
+        })
+        .collect()
+}
+
+/// Review comments left on a pull request.
+pub async fn review_comments(
+    github: &Client,
+    pr: &PullRequest,
+) -> eyre::Result<Vec<ReviewComment>> {
+    let PullRequest { org, repo, number } = pr;
+    let first = github
+        .get(
+            format!("repos/{org}/{repo}/pulls/{number}/comments"),
+            Some(&[("per_page", "100")]),
+        )
+        .await?;
+    all_pages(github, first).await
+}
+
 #[derive(Deserialize, Clone)]
 pub struct Config {
+    /// The main PR author; see also the top-level `authors`.
     pub user: String,
-    pub org: String,
-    /// Map from GitHub usernames to Starry emails
+    /// The REST API URL for GitHub Enterprise Server, like
+    /// `https://github.example.com/api/v3`. Defaults to GitHub.com.
+    pub api_url: Option<Url>,
+    /// An organization to search for PRs in; shorthand for a single entry
+    /// in `orgs`.
+    pub org: Option<String>,
+    /// Organizations (or users) to search for PRs in.
+    #[serde(default)]
+    pub orgs: Vec<String>,
+    /// Repositories (`owner/repo`) to search for PRs in, in addition to
+    /// `orgs`.
+    #[serde(default)]
+    pub repos: Vec<String>,
+    /// Patterns for `owner/repo` names to ignore PRs in. `*` matches any
+    /// characters and `?` matches one character.
+    #[serde(default)]
+    pub exclude_repos: Vec<String>,
+    /// Map from GitHub usernames to Bonusly emails
     #[serde(default)]
     pub emails: HashMap<String, String>,
+    /// Email domains (e.g. `example.com`) to trust when they're listed on a
+    /// GitHub profile.
+    #[serde(default)]
+    pub email_domains: Vec<String>,
+    /// Minimum confidence (from 0 to 1) for a fuzzy match between a GitHub
+    /// user and a Bonusly user to be used automatically.
+    #[serde(default = "match_threshold_default")]
+    pub match_threshold: f64,
+}
+
+fn match_threshold_default() -> f64 {
+    0.8
 }
 
 impl Config {
+    /// Is `email` in one of `email_domains`?
+    pub fn is_company_email(&self, email: &str) -> bool {
+        match email.rsplit_once('@') {
+            Some((_, domain)) => self
+                .email_domains
+                .iter()
+                .any(|allowed| allowed.trim_start_matches('@').eq_ignore_ascii_case(domain)),
+            None => false,
+        }
+    }
+
+    /// `api_url` with a trailing slash, so that API paths are joined onto it
+    /// rather than replacing its last segment.
+    pub fn api_url(&self) -> Option<Url> {
+        self.api_url.as_ref().map(|url| {
+            let mut url = url.clone();
+            if !url.path().ends_with('/') {
+                url.set_path(&format!("{}/", url.path()));
+            }
+            url
+        })
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.search_scopes().is_empty() {
+            return Err(eyre::eyre!(
+                "No GitHub orgs or repos to search; set `github.orgs` or `github.repos`"
+            ));
+        }
+        let is_owner_repo = |repo: &str| match repo.split_once('/') {
+            Some((owner, name)) => !owner.is_empty() && !name.is_empty() && !name.contains('/'),
+            None => false,
+        };
+        if let Some(repo) = self.repos.iter().find(|repo| !is_owner_repo(repo)) {
+            return Err(eyre::eyre!(
+                "github.repos entry {repo:?} isn't of the form `owner/repo`"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Search qualifiers for each org and repo to search, like `org:foo` and
+    /// `repo:foo/bar`.
+    fn search_scopes(&self) -> Vec<String> {
+        let mut scopes: Vec<String> = Vec::new();
+        for org in self.org.iter().chain(&self.orgs) {
+            let scope = format!("org:{org}");
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        for repo in &self.repos {
+            let scope = format!("repo:{repo}");
+            if !scopes.contains(&scope) {
+                scopes.push(scope);
+            }
+        }
+        scopes
+    }
+
+    /// Whether PRs in `owner/repo` should be ignored.
+    pub fn is_excluded(&self, owner: &str, repo: &str) -> bool {
+        let name = format!("{owner}/{repo}");
+        self.exclude_repos
+            .iter()
+            .any(|pattern| glob_matches(pattern, &name))
+    }
+
+    /// Approved PRs updated since `datetime` in any of the configured orgs
+    /// and repos, except those in excluded repos.
+    ///
+    /// Each org and repo is searched separately, since the search API
+    /// limits the length of a query.
     pub async fn prs_since(
         &self,
-        github: &Octocrab,
+        github: &impl ReviewSource,
         datetime: &DateTime<Utc>,
-    ) -> Result<Page<Issue>, octocrab::Error> {
-        github
-            .search()
-            .issues_and_pull_requests(&format!(
-                "is:pr author:{} review:approved org:{} updated:>={}",
-                self.user,
-                self.org,
-                datetime.to_rfc3339()
-            ))
-            .send()
-            .await
+    ) -> eyre::Result<Vec<PullRequest>> {
+        let mut ret: Vec<PullRequest> = Vec::new();
+        for scope in self.search_scopes() {
+            let prs = github
+                .search_prs(&format!(
+                    "is:pr author:{} review:approved {} updated:>={}",
+                    self.user,
+                    scope,
+                    datetime.to_rfc3339()
+                ))
+                .await?;
+            for pr in prs {
+                if !self.is_excluded(&pr.org, &pr.repo) && !ret.contains(&pr) {
+                    ret.push(pr);
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Case-insensitively match `name` against a pattern where `*` matches any
+/// characters (including none) and `?` matches exactly one.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`, if matching fails later.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
     }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
+/// The owner and name of an issue's repository.
 pub fn org_repo(pr: &Issue) -> Option<(&str, &str)> {
-    let mut segments = pr.repository_url.path_segments()?;
-    segments.next(); // "repo"
+    // Like `https://api.github.com/repos/{org}/{repo}`, with a prefix like
+    // `/api/v3` on GitHub Enterprise Server.
+    let mut segments = pr
+        .repository_url
+        .path_segments()?
+        .skip_while(|segment| *segment != "repos");
+    segments.next(); // "repos"
     Some((segments.next()?, segments.next()?))
 }
 
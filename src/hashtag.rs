@@ -0,0 +1,193 @@
+//! Choosing a hashtag for each bonus.
+use std::collections::BTreeMap;
+
+use color_eyre::eyre;
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::condition::Condition;
+use crate::github;
+
+/// The `[hashtags]` config section.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Config {
+    #[serde(flatten)]
+    pub policy: Policy,
+    /// The first matching rule's hashtag is used instead of `policy`.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// How to pick a hashtag when no rule matches.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum Policy {
+    /// Pick any of the company's hashtags at random.
+    #[default]
+    Random,
+    /// Always use the same hashtag.
+    Fixed { hashtag: String },
+    /// Pick from a list of hashtags at random, weighted.
+    Weighted { weights: BTreeMap<String, u32> },
+    /// Cycle through a list of hashtags, or all of the company's hashtags.
+    RoundRobin {
+        #[serde(default)]
+        hashtags: Vec<String>,
+    },
+}
+
+/// A hashtag to use for PRs matching all of the rule's conditions.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(flatten)]
+    pub condition: Condition,
+    pub hashtag: String,
+}
+
+/// What hashtag rules can match on.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Context<'a> {
+    pub pr: Option<&'a github::PullRequest>,
+    pub details: Option<&'a github::PullRequestDetails>,
+}
+
+/// Add the leading `#` to `hashtag` if it's missing.
+fn normalize(hashtag: &str) -> String {
+    if hashtag.starts_with('#') {
+        hashtag.to_owned()
+    } else {
+        format!("#{hashtag}")
+    }
+}
+
+fn is_company_hashtag(company: &[String], hashtag: &str) -> bool {
+    company
+        .iter()
+        .any(|known| known.eq_ignore_ascii_case(hashtag))
+}
+
+impl Rule {
+    fn matches(&self, context: Context<'_>) -> bool {
+        context
+            .pr
+            .is_some_and(|pr| self.condition.matches(pr, context.details))
+    }
+}
+
+impl Config {
+    /// Every hashtag named in the config.
+    fn configured_hashtags(&self) -> Vec<&str> {
+        let from_policy: Vec<&str> = match &self.policy {
+            Policy::Random => Vec::new(),
+            Policy::Fixed { hashtag } => vec![hashtag],
+            Policy::Weighted { weights } => weights.keys().map(String::as_str).collect(),
+            Policy::RoundRobin { hashtags } => hashtags.iter().map(String::as_str).collect(),
+        };
+        from_policy
+            .into_iter()
+            .chain(self.rules.iter().map(|rule| rule.hashtag.as_str()))
+            .collect()
+    }
+
+    /// Check that every configured hashtag is one of the company's hashtags.
+    pub fn validate(&self, company: &[String]) -> eyre::Result<()> {
+        if let Policy::Weighted { weights } = &self.policy {
+            if weights.values().all(|weight| *weight == 0) {
+                return Err(eyre::eyre!("hashtags.weights has no nonzero weights"));
+            }
+        }
+        if company.is_empty() {
+            // Not fetched yet.
+            return Ok(());
+        }
+        let unknown: Vec<String> = self
+            .configured_hashtags()
+            .into_iter()
+            .map(normalize)
+            .filter(|hashtag| !is_company_hashtag(company, hashtag))
+            .collect();
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(eyre::eyre!(
+                "Configured hashtags aren't Bonusly hashtags for your company: {}; \
+                 valid hashtags are: {}",
+                unknown.join(", "),
+                company.join(", ")
+            ))
+        }
+    }
+
+    /// Whether choosing a hashtag needs PR details from GitHub.
+    pub fn needs_details(&self) -> bool {
+        self.rules.iter().any(|rule| rule.condition.needs_details())
+    }
+
+    /// Choose a hashtag that's one of the company's hashtags.
+    ///
+    /// `round_robin` is the number of hashtags previously chosen with the
+    /// round-robin policy, and is incremented when it's used.
+    pub fn choose(
+        &self,
+        context: Context<'_>,
+        company: &[String],
+        round_robin: &mut usize,
+    ) -> eyre::Result<String> {
+        let valid = |hashtag: &str| {
+            let hashtag = normalize(hashtag);
+            is_company_hashtag(company, &hashtag).then_some(hashtag)
+        };
+
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(context)) {
+            if let Some(hashtag) = valid(&rule.hashtag) {
+                return Ok(hashtag);
+            }
+        }
+
+        let mut rng = rand::thread_rng();
+        let chosen = match &self.policy {
+            Policy::Random => company.choose(&mut rng).cloned(),
+            Policy::Fixed { hashtag } => valid(hashtag),
+            Policy::Weighted { weights } => {
+                let (hashtags, weights): (Vec<String>, Vec<u32>) = weights
+                    .iter()
+                    .filter_map(|(hashtag, weight)| Some((valid(hashtag)?, *weight)))
+                    .unzip();
+                WeightedIndex::new(&weights)
+                    .ok()
+                    .map(|index| hashtags[index.sample(&mut rng)].clone())
+            }
+            Policy::RoundRobin { hashtags } => {
+                let hashtags: Vec<String> = if hashtags.is_empty() {
+                    company.to_vec()
+                } else {
+                    hashtags
+                        .iter()
+                        .filter_map(|hashtag| valid(hashtag))
+                        .collect()
+                };
+                if hashtags.is_empty() {
+                    None
+                } else {
+                    let hashtag = hashtags[*round_robin % hashtags.len()].clone();
+                    *round_robin = round_robin.wrapping_add(1);
+                    Some(hashtag)
+                }
+            }
+        };
+
+        chosen.ok_or_else(|| {
+            eyre::eyre!(
+                "No valid Bonusly hashtag available; check the `[hashtags]` config against \
+                 your company's hashtags: {}",
+                if company.is_empty() {
+                    "(none)".to_owned()
+                } else {
+                    company.join(", ")
+                }
+            )
+        })
+    }
+}
@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display};
 use std::time::Duration;
 use std::{
     collections::HashSet,
@@ -10,104 +10,182 @@ use std::{
 
 use chrono::prelude::*;
 use color_eyre::eyre::{self, WrapErr};
-use rand::prelude::*;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
+pub mod amount;
 pub mod api;
+mod backend;
+mod backfill;
 pub mod bonusly;
+mod budget;
+pub mod condition;
 mod config;
 mod credentials;
+pub mod fake;
 pub mod github;
+pub mod hashtag;
+mod lock;
+mod matching;
+mod migrate;
+pub mod reason;
+mod reconcile;
+mod resolve;
+mod status;
+pub use backend::*;
+pub use backfill::*;
+pub use budget::*;
 pub use config::*;
 pub use credentials::*;
+pub use lock::*;
+pub use matching::*;
+pub use migrate::*;
+pub use reconcile::*;
+pub use resolve::*;
+pub use status::*;
 
 #[derive(Debug)]
 pub enum ReviewStatus {
-    Ok(github::NonRepliedReview, bonusly::Bonus),
+    /// A review to send a bonus for. The last field is the review's URL.
+    Ok(github::NonRepliedReview, bonusly::Bonus, String),
+    /// Several reviews to send a single bonus for; see [`batch_reviews`].
+    Batch(Vec<github::NonRepliedReview>, bonusly::Bonus),
     MissingEmail(github::NonRepliedReview),
 }
 
-pub struct Program {
-    pub credentials: Credentials,
+/// Sends cherries for approved reviews.
+///
+/// Generic over where reviews come from and where bonuses are sent, which
+/// default to GitHub and Bonusly; see [`ReviewSource`] and [`RewardSink`].
+pub struct Program<S = github::Client, R = bonusly::Client> {
+    pub credentials: Credentials<S, R>,
     pub config: Config,
+    /// If true, log the bonuses that would be sent instead of sending them,
+    /// and never write the state file.
+    pub dry_run: bool,
     state: State,
+    /// Map from author to cherries left to give this month, if Bonusly
+    /// reported it. Refreshed before each check.
+    giving_balances: HashMap<String, Option<usize>>,
+    /// Held for the life of the program so no other instance uses the same
+    /// state file. Dry runs never write the state file, so they don't lock
+    /// it.
+    _lock: Option<StateLock>,
 }
 
 impl Program {
     #[instrument(level = "debug")]
-    pub async fn from_config_path(config_path: PathBuf) -> eyre::Result<Self> {
+    pub async fn from_config_path(config_path: PathBuf, dry_run: bool) -> eyre::Result<Self> {
         info!(?config_path, "Reading configuration");
         let config = Config::from_path(config_path.clone())
             .with_context(|| format!("Failed to read config from {config_path:?}"))?;
 
-        let credentials_path = &config.credentials_path;
-        info!(?credentials_path, "Reading credentials");
-        let credentials: Credentials =
-            toml::de::from_str(&fs::read_to_string(credentials_path).with_context(|| {
-                format!("Failed to read credentials from {credentials_path:?}")
-            })?)?;
+        let credentials = Credentials::from_config(&config)?;
+        Self::new(config, credentials, dry_run).await
+    }
+}
 
+impl<S: ReviewSource, R: RewardSink> Program<S, R> {
+    /// Lock and read the state file, creating it if it doesn't exist.
+    ///
+    /// Dry runs don't take the lock, so they can run alongside the daemon.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn new(
+        config: Config,
+        credentials: Credentials<S, R>,
+        dry_run: bool,
+    ) -> eyre::Result<Self> {
         let state_path = &config.state_path;
+        if let Some(parent) = state_path.parent() {
+            info!(?parent, "Ensuring state parent dir exists");
+            fs::create_dir_all(parent)?;
+        }
+        let lock = if dry_run {
+            debug!("Dry run; not locking state file");
+            None
+        } else {
+            let lock = StateLock::acquire(&sibling_path(state_path, "lock"))?;
+            debug!(lock_path = ?lock.path(), "Locked state file");
+            Some(lock)
+        };
+
         info!(?state_path, "Reading program state");
-        let state = State::from_data_path(state_path, &credentials, &config)
+        let state = State::from_data_path(state_path, &credentials.bonusly, &config)
             .await
             .with_context(|| format!("Failed to read state from {state_path:?}"))?;
-        Ok(Self {
+        for author in config.author_configs() {
+            credentials.bonusly_for(&config, &author.github.user)?;
+            author.hashtags.validate(&state.hashtags)?;
+        }
+        let mut ret = Self {
             config,
             credentials,
+            dry_run,
             state,
-        })
+            giving_balances: HashMap::new(),
+            _lock: lock,
+        };
+        for author in ret.config.author_configs() {
+            ret.reconcile_in_flight(&author.github.user)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to check whether {}'s unfinished bonuses were sent",
+                        author.github.user
+                    )
+                })?;
+        }
+        ret.write_state().await?;
+        Ok(ret)
     }
 
     async fn write_state(&self) -> eyre::Result<()> {
+        if self.dry_run {
+            debug!("Dry run; not writing state");
+            return Ok(());
+        }
         self.state.write_to_path(&self.config.state_path).await?;
         Ok(())
     }
 
-    #[instrument(skip_all, level = "debug")]
+    /// Approved reviews we haven't replied to, including pending reviews from
+    /// the state file.
+    ///
+    /// With a `backfill` window, only reviews submitted in the window are
+    /// returned, and pending reviews are left to the regular check.
+    #[instrument(skip_all, fields(author = %config.github.user), level = "debug")]
     async fn new_approved_reviews(
         &self,
+        config: &Config,
+        backfill: Option<&Backfill>,
     ) -> eyre::Result<HashMap<github::PullRequest, Vec<github::Review>>> {
         let mut ret = HashMap::new();
-        let updated_prs = self
-            .config
+        let author = self.state.author(&config.github.user);
+        let since = backfill.map_or(&self.state.cutoff, |backfill| &backfill.since);
+        let updated_prs = config
             .github
-            .prs_since(&self.credentials.github, &self.state.cutoff)
+            .prs_since(&self.credentials.github, since)
             .await?;
-        for pr in updated_prs.items {
-            let (org, repo) = github::org_repo(&pr).ok_or_else(|| {
-                eyre::eyre!("Couldn't parse org/repo from url {}", &pr.repository_url)
-            })?;
-
-            let reviews: github::Page<github::Review> = self
-                .credentials
-                .github
-                .pulls(org, repo)
-                // why does this api use different types for pr numbers and pr ids
-                // and then use the wrong one
-                .list_reviews(pr.number.try_into().unwrap())
-                .await?;
+        for pull_request in updated_prs {
+            let reviews = self.credentials.github.list_reviews(&pull_request).await?;
 
             let approved_reviews = reviews
-                .items
                 .into_iter()
+                .filter(|review| {
+                    backfill.is_none_or(|backfill| backfill.contains(review.submitted_at))
+                })
                 .filter(|review| {
                     matches!(review.state, Some(github::ReviewState::Approved))
-                        && !self.state.replied_prs.contains(&github::RepliedReview {
-                            pr: github::PullRequest {
-                                org: org.to_owned(),
-                                repo: repo.to_owned(),
-                                number: pr.number,
-                            },
+                        && !author.replied_prs.contains(&github::RepliedReview {
+                            pr: pull_request.clone(),
                             reviewer: review.user.login.clone(),
                         })
                 })
                 .inspect(|review| {
                     debug!(
-                        ?org,
-                        ?repo,
-                        pr_number = pr.number,
+                        org = %pull_request.org,
+                        repo = %pull_request.repo,
+                        pr_number = pull_request.number,
                         reviewer = %review.user.login,
                         review_id = %review.id,
                         "Found approved/unreplied review"
@@ -116,47 +194,32 @@ impl Program {
                 .collect::<Vec<_>>();
 
             if !approved_reviews.is_empty() {
-                ret.insert(
-                    github::PullRequest {
-                        org: org.to_owned(),
-                        repo: repo.to_owned(),
-                        number: pr.number,
-                    },
-                    approved_reviews,
-                );
+                ret.insert(pull_request, approved_reviews);
             }
         }
 
+        if backfill.is_some() {
+            return Ok(ret);
+        }
         for github::NonRepliedReview {
             pr,
             reviewer: _reviewer,
             id,
-        } in &self.state.non_replied_prs
+        } in &author.non_replied_prs
         {
-            let github::PullRequest { org, repo, number } = pr;
-            let review: github::Review = self
-                .credentials
-                .github
-                .get(
-                    format!("/repos/{org}/{repo}/pulls/{number}/reviews/{id}"),
-                    None::<&()>,
-                )
-                .await?;
-            ret.entry(github::PullRequest {
-                org: org.clone(),
-                repo: repo.clone(),
-                number: *number,
-            })
-            .or_default()
-            .push(review);
+            let review = self.credentials.github.review(pr, *id).await?;
+            ret.entry(pr.clone()).or_default().push(review);
         }
 
         Ok(ret)
     }
 
-    #[instrument(skip_all, level = "debug")]
-    async fn reviews(&mut self) -> eyre::Result<Vec<ReviewStatus>> {
-        let mut rng = rand::thread_rng();
+    #[instrument(skip_all, fields(author = %config.github.user), level = "debug")]
+    async fn reviews(
+        &mut self,
+        config: &Config,
+        backfill: Option<&Backfill>,
+    ) -> eyre::Result<Vec<ReviewStatus>> {
         let mut ret = Vec::new();
 
         // TODO:
@@ -164,15 +227,35 @@ impl Program {
         //  - think about error handling, particularly re: the state file
         //  - investigate cool parallel shit here with rayon
 
-        for (pr, reviews) in self.new_approved_reviews().await? {
+        for (pr, reviews) in self.new_approved_reviews(config, backfill).await? {
+            let details = if config.amount.has_rules()
+                || config.reasons.needs_details()
+                || config.hashtags.needs_details()
+            {
+                Some(self.credentials.github.pr_details(&pr).await?)
+            } else {
+                None
+            };
+            let comments = if config.amount.uses_review_comments() {
+                self.credentials.github.review_comments(&pr).await?
+            } else {
+                Vec::new()
+            };
+
             for review in reviews {
+                if self.state.ignored_reviewers.contains(&review.user.login) {
+                    debug!(reviewer = %review.user.login, "Skipping ignored reviewer");
+                    continue;
+                }
                 let user = self
                     .state
-                    .github_user(review.user.login.clone(), &self.credentials)
+                    .github_user(review.user.login.clone(), &self.credentials.github)
                     .await?;
-                let email = self
-                    .config
-                    .find_bonusly_email(&self.state.bonusly_users, &user);
+                let email = config.find_bonusly_email(
+                    &self.state.bonusly_users,
+                    &self.state.learned_emails,
+                    &user,
+                );
 
                 let missing_email = github::NonRepliedReview {
                     pr: github::PullRequest {
@@ -184,24 +267,75 @@ impl Program {
                     id: review.id,
                 };
 
+                let amount = config.amount.amount(details.as_ref().map(|details| {
+                    amount::Attributes {
+                        pr: &pr,
+                        details,
+                        review_comments: comments
+                            .iter()
+                            .filter(|comment| comment.user.login == missing_email.reviewer)
+                            .count(),
+                    }
+                }));
+
+                let reason = config.reasons.reason(&reason::Context {
+                    pr: &pr,
+                    details: details.as_ref(),
+                    reviewer_name: user.name.as_deref().unwrap_or(&user.login),
+                    review_url: &review.html_url,
+                });
+
                 match email {
-                    Some(email) => {
-                        info!(review = ?missing_email, "Found email for review");
-                        self.state.non_replied_prs.remove(&missing_email);
+                    BonuslyMatch::Found(candidate) => {
+                        info!(
+                            review = ?missing_email,
+                            email = %candidate.email,
+                            confidence = candidate.confidence,
+                            "Found email for review"
+                        );
+                        let hashtag = config.hashtags.choose(
+                            hashtag::Context {
+                                pr: Some(&pr),
+                                details: details.as_ref(),
+                            },
+                            &self.state.hashtags,
+                            &mut self.state.hashtag_round_robin,
+                        )?;
+                        self.state
+                            .author_mut(&config.github.user)
+                            .non_replied_prs
+                            .remove(&missing_email);
+                        self.state
+                            .unmatched_candidates
+                            .remove(&missing_email.reviewer);
                         ret.push(ReviewStatus::Ok(
                             missing_email,
                             bonusly::Bonus {
-                                receiver_email: email,
-                                amount: self.config.cherries_per_check,
-                                hashtag: self.state.hashtags
-                                    [rng.gen_range(0..self.state.hashtags.len())]
-                                .clone(),
-                                reason: format!("thanks for approving my PR! {}", review.html_url),
+                                receiver_email: candidate.email,
+                                amount,
+                                hashtag,
+                                reason,
                             },
+                            review.html_url,
                         ))
                     }
-                    None => {
-                        if !self.state.non_replied_prs.contains(&missing_email) {
+                    BonuslyMatch::Unresolved(candidates) => {
+                        if !candidates.is_empty() {
+                            info!(
+                                reviewer = %missing_email.reviewer,
+                                ?candidates,
+                                "Ambiguous Bonusly match for reviewer"
+                            );
+                        }
+                        self.state
+                            .unmatched_candidates
+                            .insert(missing_email.reviewer.clone(), candidates);
+                        if !self
+                            .state
+                            .author(&config.github.user)
+                            .non_replied_prs
+                            .contains(&missing_email)
+                        {
                             info!(?missing_email, "Missing email for review");
                             ret.push(ReviewStatus::MissingEmail(missing_email));
                         }
@@ -212,90 +346,264 @@ impl Program {
         Ok(ret)
     }
 
-    #[instrument(skip(self), level = "debug")]
-    async fn reply(&mut self, review: ReviewStatus) -> eyre::Result<()> {
+    #[instrument(skip(self, config), fields(author = %config.github.user), level = "debug")]
+    async fn reply(&mut self, config: &Config, review: ReviewStatus) -> eyre::Result<()> {
         match review {
-            ReviewStatus::Ok(review, bonus) => {
-                if self.state.replied_prs.contains(&review.clone().into()) {
-                    // Already replied to this PR-reviewer combo; this can
-                    // happen if a reviewer approves a PR twice in one "check
-                    // interval", because `new_approved_reviews` doesn't mutate
-                    // `self.state.replied_prs`.
-                    return Ok(());
-                }
-                let result = self.credentials.bonusly.send_bonus(&bonus).await;
-                tokio::time::sleep(self.config.send_bonus_interval).await;
-                match result {
-                    Ok(reply) => {
-                        info!(?reply, "Sent cherries");
-                        self.state.replied_prs.insert(review.into());
-                    }
-                    Err(err) => {
-                        info!(?err, "Failed to send bonus");
-                        self.state.non_replied_prs.insert(review);
-                        return Err(err);
-                    }
-                }
-            }
+            ReviewStatus::Ok(review, bonus, _) => self.send(config, vec![review], bonus).await?,
+            ReviewStatus::Batch(reviews, bonus) => self.send(config, reviews, bonus).await?,
             ReviewStatus::MissingEmail(missing_email) => {
                 info!(
                     user = %missing_email.reviewer,
                     "No email found for GitHub reviewer"
                 );
-                self.state.non_replied_prs.insert(missing_email);
+                if !self.dry_run {
+                    self.state
+                        .author_mut(&config.github.user)
+                        .non_replied_prs
+                        .insert(missing_email);
+                }
             }
         }
 
         Ok(())
     }
 
+    /// Send `bonus` for `reviews` and mark each of them replied to.
+    async fn send(
+        &mut self,
+        config: &Config,
+        reviews: Vec<github::NonRepliedReview>,
+        bonus: bonusly::Bonus,
+    ) -> eyre::Result<()> {
+        let author = &config.github.user;
+        if reviews.iter().all(|review| {
+            self.state
+                .author(author)
+                .replied_prs
+                .contains(&review.clone().into())
+        }) {
+            // Already replied to this PR-reviewer combo; this can
+            // happen if a reviewer approves a PR twice in one "check
+            // interval", because `new_approved_reviews` doesn't mutate
+            // `self.state.replied_prs`.
+            return Ok(());
+        }
+        if let Some(balance) = self.giving_balance(author) {
+            if bonus.amount > balance {
+                warn!(
+                    balance,
+                    amount = bonus.amount,
+                    "Not enough cherries left to send bonus"
+                );
+                if !self.dry_run {
+                    self.state
+                        .author_mut(author)
+                        .non_replied_prs
+                        .extend(reviews);
+                }
+                return Err(eyre::eyre!(
+                    "Can't send {} cherries to {}; only {balance} left this month",
+                    bonus.amount,
+                    bonus.receiver_email
+                ));
+            }
+        }
+        if self.state.author(author).is_in_flight(&reviews) {
+            // A previous send's outcome is unknown; don't risk sending twice.
+            warn!(
+                receiver_email = %bonus.receiver_email,
+                "Bonus for these reviews may already have been sent; waiting to reconcile"
+            );
+            return Ok(());
+        }
+        self.send_bonus(author, reviews, &bonus, config.send_bonus_interval)
+            .await
+    }
+
+    /// Check for new reviews, send cherries for them, refresh cached Bonusly
+    /// data if needed, and write the state file.
+    ///
+    /// If any bonuses fail to send, the rest are still sent and a
+    /// [`SendErrors`] is returned.
     #[instrument(skip_all, level = "debug")]
-    pub async fn reply_all_and_wait(&mut self) -> eyre::Result<()> {
-        let reviews = self.reviews().await?;
+    pub async fn reply_all(&mut self) -> eyre::Result<()> {
+        let check_started = Utc::now();
+        let mut errors = Vec::new();
+        let mut check_error = None;
+        for config in self.config.author_configs() {
+            match self.reply_all_for(&config).await {
+                Ok(mut author_errors) => errors.append(&mut author_errors),
+                Err(err) => {
+                    error!(
+                        author = %config.github.user,
+                        "Error while checking for reviews: {:?}", err
+                    );
+                    check_error.get_or_insert(err);
+                }
+            }
+        }
+
+        let result = self
+            .state
+            .maybe_update(&self.credentials.bonusly, &self.config)
+            .await;
+        if check_error.is_none() {
+            // Only move the cutoff forward if every author was checked, so
+            // that reviews aren't missed.
+            self.state.cutoff = check_started;
+        }
+        self.write_state().await?;
+        result?;
+
+        if let Some(err) = check_error {
+            Err(err)
+        } else if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SendErrors(errors).into())
+        }
+    }
+
+    /// Check for new reviews of one author's PRs and send cherries for them.
+    /// Returns errors from sending individual bonuses.
+    #[instrument(skip_all, fields(author = %config.github.user), level = "debug")]
+    async fn reply_all_for(&mut self, config: &Config) -> eyre::Result<Vec<eyre::Report>> {
+        self.reconcile_in_flight(&config.github.user).await?;
+        self.refresh_giving_balance(config).await?;
+        let mut reviews = self.reviews(config, None).await?;
+        if config.batch_bonuses {
+            reviews = batch_reviews(reviews);
+        }
         if !reviews.is_empty() {
             info!(?reviews, "Sending cherries for reviews");
         }
         let mut errors = Vec::with_capacity(reviews.len());
         for review in reviews {
-            if let Err(err) = self.reply(review).await {
+            if let Err(err) = self.reply(config, review).await {
                 error!("Error while sending cherries: {:?}", err);
                 errors.push(err);
             }
 
+            if self.dry_run {
+                continue;
+            }
             let duration = Duration::from_secs(10);
             debug!("Sleeping {:?} before sending next bonus", duration);
             tokio::time::sleep(duration).await;
         }
 
-        let result = self
-            .state
-            .maybe_update(&self.credentials, &self.config)
-            .await;
-        self.state.cutoff = Utc::now();
-        self.write_state().await?;
-        result?;
+        if let Err(err) = self.maybe_spend_down(config).await {
+            error!("Error while spending down balance: {:?}", err);
+            errors.push(err);
+        }
+
+        Ok(errors)
+    }
+
+    /// [`Program::reply_all`], then sleep for the configured PR check
+    /// interval.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn reply_all_and_wait(&mut self) -> eyre::Result<()> {
+        let result = self.reply_all().await;
 
         debug!("Sleeping for {:?}", self.config.pr_check_interval);
         tokio::time::sleep(self.config.pr_check_interval).await;
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            // TODO this is probably really ugly formatting
-            Err(eyre::eyre!("{:?}", errors))
+        result
+    }
+}
+
+/// Combine the [`ReviewStatus::Ok`] reviews for each receiver into a single
+/// [`ReviewStatus::Batch`] whose amount is the sum of the individual bonuses'
+/// and whose reason links to every review.
+///
+/// The combined reason is fixed rather than rendered from the `[reasons]`
+/// templates, which describe a single PR. Receivers with a single review are
+/// left alone.
+pub fn batch_reviews(reviews: Vec<ReviewStatus>) -> Vec<ReviewStatus> {
+    type Group = Vec<(github::NonRepliedReview, bonusly::Bonus, String)>;
+    let mut ret = Vec::with_capacity(reviews.len());
+    let mut by_receiver: Vec<(String, Group)> = Vec::new();
+    for review in reviews {
+        match review {
+            ReviewStatus::Ok(review, bonus, url) => {
+                let replied: github::RepliedReview = review.clone().into();
+                match by_receiver
+                    .iter_mut()
+                    .find(|(email, _)| *email == bonus.receiver_email)
+                {
+                    Some((_, group)) => {
+                        // Don't pay twice for a reviewer approving a PR twice.
+                        if !group.iter().any(|(other, _, _)| {
+                            github::RepliedReview::from(other.clone()) == replied
+                        }) {
+                            group.push((review, bonus, url));
+                        }
+                    }
+                    None => {
+                        by_receiver.push((bonus.receiver_email.clone(), vec![(review, bonus, url)]))
+                    }
+                }
+            }
+            other => ret.push(other),
+        }
+    }
+
+    for (receiver_email, mut group) in by_receiver {
+        if group.len() == 1 {
+            let (review, bonus, url) = group.remove(0);
+            ret.push(ReviewStatus::Ok(review, bonus, url));
+            continue;
         }
+        let amount = group.iter().map(|(_, bonus, _)| bonus.amount).sum();
+        let hashtag = group[0].1.hashtag.clone();
+        let reason = format!(
+            "thanks for approving {} of my PRs! {}",
+            group.len(),
+            group
+                .iter()
+                .map(|(_, _, url)| url.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+        ret.push(ReviewStatus::Batch(
+            group.into_iter().map(|(review, _, _)| review).collect(),
+            bonusly::Bonus {
+                receiver_email,
+                amount,
+                hashtag,
+                reason,
+            },
+        ));
     }
+    ret
 }
 
+/// Errors encountered while sending individual bonuses in
+/// [`Program::reply_all`].
+#[derive(Debug)]
+pub struct SendErrors(pub Vec<eyre::Report>);
+
+impl Display for SendErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to send {} bonuses:", self.0.len())?;
+        for err in &self.0 {
+            write!(f, "\n  - {err:#}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SendErrors {}
+
 /// Program state. Deserialized from data dir.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct State {
+    /// Version of the state file format; see [`migrate_state`].
+    version: u64,
     last_update: DateTime<Utc>,
-    /// PR-reviewer combos we've already replied to; don't send cherries more
-    /// than once per reviewer per PR.
-    replied_prs: HashSet<github::RepliedReview>,
-    /// Reviews we haven't replied to; missing emails or API errors.
-    non_replied_prs: HashSet<github::NonRepliedReview>,
+    /// State for each PR author, by GitHub username.
+    authors: BTreeMap<String, AuthorState>,
     /// "Don't look for PRs before this datetime"
     cutoff: DateTime<Utc>,
     /// All bonusly users, for correlation with GitHub users.
@@ -304,88 +612,187 @@ pub struct State {
     github_members: HashMap<String, github::User>,
     /// Bonusly hashtags.
     hashtags: Vec<String>,
+    /// Map from GitHub username to possible Bonusly users, for reviewers we
+    /// couldn't match confidently.
+    unmatched_candidates: HashMap<String, Vec<Candidate>>,
+    /// Map from GitHub username to Bonusly email, chosen with the `resolve`
+    /// command.
+    learned_emails: HashMap<String, String>,
+    /// GitHub usernames to never send cherries to, chosen with the `resolve`
+    /// command.
+    ignored_reviewers: HashSet<String>,
+    /// Number of hashtags chosen with the round-robin hashtag policy.
+    hashtag_round_robin: usize,
+}
+
+/// Program state for a single PR author.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct AuthorState {
+    /// PR-reviewer combos we've already replied to; don't send cherries more
+    /// than once per reviewer per PR.
+    replied_prs: HashSet<github::RepliedReview>,
+    /// Reviews we haven't replied to; missing emails or API errors.
+    non_replied_prs: HashSet<github::NonRepliedReview>,
+    /// Cherries sent this month, for spending down the balance at the end of
+    /// the month.
+    monthly_approvals: MonthlyApprovals,
+    /// Bonuses being sent. Written before sending each bonus, so that if the
+    /// program dies before recording the result, the next start can check
+    /// whether it was sent; see [`Program::reconcile_in_flight`].
+    in_flight: Vec<InFlightBonus>,
 }
 
 impl State {
+    /// The state for `author`, which is empty if we haven't checked their
+    /// PRs yet.
+    fn author(&self, author: &str) -> &AuthorState {
+        static EMPTY: std::sync::OnceLock<AuthorState> = std::sync::OnceLock::new();
+        self.authors
+            .get(author)
+            .unwrap_or_else(|| EMPTY.get_or_init(Default::default))
+    }
+
+    fn author_mut(&mut self, author: &str) -> &mut AuthorState {
+        self.authors.entry(author.to_owned()).or_default()
+    }
+
     #[instrument(skip_all, level = "debug")]
-    pub async fn new(credentials: &Credentials, config: &Config) -> eyre::Result<Self> {
+    pub async fn new(bonusly: &impl RewardSink, config: &Config) -> eyre::Result<Self> {
         let mut ret = Self {
+            version: CURRENT_STATE_VERSION,
             cutoff: Utc::now() - chrono::Duration::from_std(config.pr_check_interval).unwrap(),
             last_update: Utc::now(),
-            replied_prs: Default::default(),
+            authors: Default::default(),
             bonusly_users: Default::default(),
             github_members: Default::default(),
             hashtags: Default::default(),
-            non_replied_prs: Default::default(),
+            unmatched_candidates: Default::default(),
+            learned_emails: Default::default(),
+            ignored_reviewers: Default::default(),
+            hashtag_round_robin: 0,
         };
-        ret.update(credentials, config).await?;
+        ret.update(bonusly, config).await?;
         Ok(ret)
     }
 
     #[instrument(skip_all, level = "debug")]
     pub async fn update(
         &mut self,
-        credentials: &Credentials,
+        bonusly: &impl RewardSink,
         _config: &Config,
     ) -> eyre::Result<()> {
         self.last_update = Utc::now();
-        self.hashtags = credentials.bonusly.hashtags().await?;
-        self.bonusly_users = credentials.bonusly.list_users().await?;
+        self.hashtags = bonusly.hashtags().await?;
+        self.bonusly_users = bonusly.list_users().await?;
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug")]
     pub async fn maybe_update(
         &mut self,
-        credentials: &Credentials,
+        bonusly: &impl RewardSink,
         config: &Config,
     ) -> eyre::Result<()> {
         let now = Utc::now();
         if self.last_update + config.state_update_interval <= now {
-            self.update(credentials, config).await?;
+            self.update(bonusly, config).await?;
         }
         Ok(())
     }
 
-    #[instrument(skip(credentials, config), level = "debug")]
+    #[instrument(skip(bonusly, config), level = "debug")]
     pub async fn from_data_path(
         data_path: &Path,
-        credentials: &Credentials,
+        bonusly: &impl RewardSink,
         config: &Config,
     ) -> eyre::Result<Self> {
         if let Some(parent) = data_path.parent() {
             info!(?parent, "Ensuring state parent dir exists");
             fs::create_dir_all(parent)?;
         }
-        if !data_path.exists() {
-            info!(?data_path, "State file not found, creating default");
-            let new = Self::new(credentials, config).await?;
-            serde_json::to_writer(BufWriter::new(File::create(data_path)?), &new)?;
-            Ok(new)
+        let backup_path = sibling_path(data_path, "bak");
+        if data_path.exists() {
+            match Self::read_from_path(data_path, &config.github.user) {
+                Ok(state) => Ok(state),
+                Err(err) if backup_path.exists() => {
+                    error!(
+                        ?data_path,
+                        ?backup_path,
+                        "Failed to read state file, falling back to backup! \
+                         Cherries sent since the backup was written may be sent again: {err:?}"
+                    );
+                    Self::read_from_path(&backup_path, &config.github.user).with_context(|| {
+                        format!("Failed to read state from {data_path:?} or {backup_path:?}")
+                    })
+                }
+                Err(err) => Err(err),
+            }
+        } else if backup_path.exists() {
+            error!(
+                ?data_path,
+                ?backup_path,
+                "State file not found, falling back to backup!"
+            );
+            Self::read_from_path(&backup_path, &config.github.user)
         } else {
-            Ok(serde_json::from_reader(BufReader::new(File::open(
-                data_path,
-            )?))?)
+            info!(?data_path, "State file not found, creating default");
+            Self::new(bonusly, config).await
         }
     }
 
+    /// Read a state file, migrating it from older formats if needed.
+    ///
+    /// `main_author` is the `github.user` from the config; see
+    /// [`migrate_state`].
+    pub fn read_from_path(data_path: &Path, main_author: &str) -> eyre::Result<Self> {
+        let state: serde_json::Value =
+            serde_json::from_reader(BufReader::new(File::open(data_path)?))?;
+        Ok(serde_json::from_value(migrate_state(state, main_author)?)?)
+    }
+
+    /// Write the state to `data_path` atomically.
+    ///
+    /// The state is written to a temporary file which is then renamed over
+    /// `data_path`, so a crash never leaves a truncated state file. The
+    /// previous state is kept in a `.bak` file next to `data_path`.
     #[instrument(skip(self), level = "debug")]
     pub async fn write_to_path(&self, data_path: &Path) -> eyre::Result<()> {
-        serde_json::to_writer_pretty(BufWriter::new(File::create(data_path)?), &self)?;
+        let tmp_path = sibling_path(data_path, "tmp");
+        let mut writer = BufWriter::new(
+            File::create(&tmp_path).with_context(|| format!("Failed to create {tmp_path:?}"))?,
+        );
+        serde_json::to_writer_pretty(&mut writer, &self)?;
+        writer
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()
+            .with_context(|| format!("Failed to sync {tmp_path:?}"))?;
+
+        if data_path.exists() {
+            let backup_path = sibling_path(data_path, "bak");
+            fs::copy(data_path, &backup_path)
+                .with_context(|| format!("Failed to back up state to {backup_path:?}"))?;
+        }
+        fs::rename(&tmp_path, data_path)
+            .with_context(|| format!("Failed to rename {tmp_path:?} to {data_path:?}"))?;
+        if let Some(parent) = data_path.parent() {
+            // Make sure the rename itself is durable.
+            File::open(parent)?.sync_all()?;
+        }
         Ok(())
     }
 
-    #[instrument(skip(self, credentials), level = "debug")]
+    #[instrument(skip(self, github), level = "debug")]
     pub async fn github_user(
         &mut self,
         login: String,
-        credentials: &Credentials,
+        github: &impl ReviewSource,
     ) -> eyre::Result<github::User> {
         let maybe_user = self.github_members.get(&login);
         match maybe_user {
             Some(user) => Ok(user.clone()),
             None => {
-                let user = github::User::from_login(&credentials.github, &login).await?;
+                let user = github.user(&login).await?;
                 self.github_members.insert(login.clone(), user);
                 // TODO there has got to be a better way to do this
                 Ok(self.github_members.get(&login).unwrap().clone())
@@ -393,3 +800,12 @@ impl State {
         }
     }
 }
+
+/// `path` with `.{extension}` appended to its file name; e.g. `state.json`
+/// becomes `state.json.bak`.
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
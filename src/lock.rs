@@ -0,0 +1,68 @@
+//! An exclusive lock on the state file, so that two instances don't both
+//! send cherries for the same review.
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{self, WrapErr};
+use tracing::{debug, instrument};
+
+/// An advisory lock held on a lock file for as long as this value lives.
+///
+/// The lock file contains the PID of the process holding the lock.
+#[derive(Debug)]
+pub struct StateLock {
+    path: PathBuf,
+    // Never read; held for its lock.
+    #[allow(dead_code)]
+    file: File,
+}
+
+impl StateLock {
+    /// Lock `path`, creating it if needed. Fails immediately if another
+    /// process holds the lock.
+    #[instrument(level = "debug")]
+    pub fn acquire(path: &Path) -> eyre::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {path:?}"))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+                let holder = holder.trim();
+                return Err(if holder.is_empty() {
+                    eyre::eyre!(
+                        "{path:?} is locked by another process; is cherries-4-prs already running?"
+                    )
+                } else {
+                    eyre::eyre!("{path:?} is locked by process {holder}; is cherries-4-prs already running?")
+                });
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("Failed to lock {path:?}"));
+            }
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        debug!(?path, "Acquired lock");
+
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
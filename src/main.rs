@@ -1,27 +1,231 @@
+use std::io::{self, BufRead, Write};
 use std::path::PathBuf;
+use std::process::ExitCode;
 
-use color_eyre::eyre;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{self, WrapErr};
 use structopt::StructOpt;
 
 use cherries_4_prs::*;
 
+/// Exit code for `run-once` when the check succeeded but some bonuses failed
+/// to send.
+const EXIT_SEND_FAILED: u8 = 2;
+
 #[tokio::main]
-pub async fn main() -> eyre::Result<()> {
+pub async fn main() -> eyre::Result<ExitCode> {
     let args = Opt::from_args();
     install_tracing(&args.tracing_filter);
     color_eyre::install()?;
 
-    let mut prg = Program::from_config_path(args.config.clone()).await?;
+    let command = args.command.unwrap_or(Command::Run);
+    if let Command::Status { json } = command {
+        // Only reads the state file, so it doesn't need credentials and works
+        // while the daemon is running.
+        print_status(args.config, json)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut prg = Program::from_config_path(args.config.clone(), args.dry_run).await?;
+
+    match command {
+        Command::Run => loop {
+            prg.reply_all_and_wait().await?;
+        },
+        Command::RunOnce => send_result(prg.reply_all().await),
+        Command::Backfill { since, until, yes } => {
+            let backfill = Backfill::new(since, until)?;
+            let plans = prg.plan_backfill(&backfill).await?;
+            if !print_backfill(&plans) {
+                println!("Nothing to backfill.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            if prg.dry_run {
+                return Ok(ExitCode::SUCCESS);
+            }
+            if !yes && !confirm("Send these bonuses?")? {
+                println!("Not sending anything.");
+                return Ok(ExitCode::SUCCESS);
+            }
+            send_result(prg.send_backfill(plans).await)
+        }
+        Command::Resolve => {
+            resolve(&mut prg).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Status { .. } => unreachable!("handled above"),
+    }
+}
+
+/// Print a summary of the state file, without touching it or the APIs.
+fn print_status(config_path: PathBuf, json: bool) -> eyre::Result<()> {
+    let config = Config::from_path(config_path.clone())
+        .with_context(|| format!("Failed to read config from {config_path:?}"))?;
+    let state_path = &config.state_path;
+    let status = State::read_from_path(state_path, &config.github.user)
+        .with_context(|| format!("Failed to read state from {state_path:?}"))?
+        .status();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    } else {
+        println!("{status}");
+    }
+    Ok(())
+}
+
+/// Exit with [`EXIT_SEND_FAILED`] if only some bonuses failed to send.
+fn send_result(result: eyre::Result<()>) -> eyre::Result<ExitCode> {
+    match result {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(err) => match err.downcast_ref::<SendErrors>() {
+            Some(errors) => {
+                eprintln!("{errors}");
+                Ok(ExitCode::from(EXIT_SEND_FAILED))
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// Print the bonuses a backfill would send. Returns false if there's
+/// nothing to do.
+fn print_backfill(plans: &[BackfillPlan]) -> bool {
+    let mut any = false;
+    for plan in plans {
+        if plan.reviews.is_empty() {
+            continue;
+        }
+        any = true;
+        println!();
+        print!(
+            "{}: {} cherries in {} bonuses",
+            plan.config.github.user,
+            plan.total(),
+            plan.reviews
+                .iter()
+                .filter(|review| !matches!(review, ReviewStatus::MissingEmail(_)))
+                .count()
+        );
+        match plan.giving_balance {
+            Some(balance) if balance < plan.total() => {
+                println!(" (only {balance} left this month; bonuses that don't fit will be left pending)")
+            }
+            Some(balance) => println!(" ({balance} left this month)"),
+            None => println!(),
+        }
+        for review in &plan.reviews {
+            match review {
+                ReviewStatus::Ok(review, bonus, _) => {
+                    let github::PullRequest { org, repo, number } = &review.pr;
+                    println!(
+                        "  {org}/{repo}#{number}: {} to {} {}: {}",
+                        bonus.amount, bonus.receiver_email, bonus.hashtag, bonus.reason
+                    );
+                }
+                ReviewStatus::Batch(reviews, bonus) => {
+                    println!(
+                        "  {} reviews: {} to {} {}: {}",
+                        reviews.len(),
+                        bonus.amount,
+                        bonus.receiver_email,
+                        bonus.hashtag,
+                        bonus.reason
+                    );
+                }
+                ReviewStatus::MissingEmail(review) => {
+                    let github::PullRequest { org, repo, number } = &review.pr;
+                    println!(
+                        "  {org}/{repo}#{number}: no Bonusly email for {}; will be saved for `resolve`",
+                        review.reviewer
+                    );
+                }
+            }
+        }
+    }
+    any
+}
+
+/// Ask a yes/no question on stdin; anything but "y" or "yes" is no.
+fn confirm(question: &str) -> eyre::Result<bool> {
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(matches!(line.trim(), "y" | "Y" | "yes"))
+}
+
+/// Interactively pick Bonusly users for reviewers we couldn't match.
+async fn resolve(prg: &mut Program) -> eyre::Result<()> {
+    let reviewers = prg.unresolved_reviewers();
+    if reviewers.is_empty() {
+        println!("No unresolved reviewers.");
+        return Ok(());
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    for reviewer in reviewers {
+        println!();
+        match &reviewer.name {
+            Some(name) => println!("{} ({name})", reviewer.login),
+            None => println!("{}", reviewer.login),
+        }
+        println!("  {} pending reviews:", reviewer.reviews.len());
+        for review in &reviewer.reviews {
+            let github::PullRequest { org, repo, number } = &review.pr;
+            println!("    {org}/{repo}#{number}");
+        }
+        if reviewer.candidates.is_empty() {
+            println!("  No candidate Bonusly users.");
+        } else {
+            println!("  Candidate Bonusly users:");
+            for (i, candidate) in reviewer.candidates.iter().enumerate() {
+                println!(
+                    "    {}. {} <{}> (confidence {:.2})",
+                    i + 1,
+                    candidate.full_name,
+                    candidate.email,
+                    candidate.confidence
+                );
+            }
+        }
 
-    loop {
-        prg.reply_all_and_wait().await?;
+        loop {
+            print!("Candidate number, email, [s]kip, [i]gnore, or [q]uit: ");
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            let resolution = match line.trim() {
+                "" => continue,
+                "s" | "skip" => break,
+                "q" | "quit" => return Ok(()),
+                "i" | "ignore" => Resolution::Ignore,
+                choice => match choice.parse::<usize>() {
+                    Ok(n) if (1..=reviewer.candidates.len()).contains(&n) => {
+                        Resolution::Email(reviewer.candidates[n - 1].email.clone())
+                    }
+                    Ok(_) => {
+                        println!("No candidate {choice}.");
+                        continue;
+                    }
+                    Err(_) => Resolution::Email(choice.to_owned()),
+                },
+            };
+            match prg.resolve_reviewer(&reviewer.login, resolution).await {
+                Ok(()) => break,
+                Err(err) => println!("{err}"),
+            }
+        }
     }
+    Ok(())
 }
 
 fn install_tracing(filter_directives: &str) {
     use tracing_subscriber::prelude::*;
     use tracing_subscriber::{
-        fmt::{self, format::FmtSpan, time::UtcTime},
+        fmt::{self, format::FmtSpan},
         EnvFilter,
     };
 
@@ -50,6 +254,53 @@ struct Opt {
     /// See: https://docs.rs/tracing-subscriber/latest/tracing_subscriber/struct.EnvFilter.html
     #[structopt(long, default_value = "info")]
     tracing_filter: String,
+    /// Don't send any cherries or write the state file; log the bonuses that
+    /// would be sent instead.
+    #[structopt(long)]
+    dry_run: bool,
     /// Configuration path (TOML).
     config: PathBuf,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Check for approved PRs and send cherries forever. This is the default.
+    Run,
+    /// Check for approved PRs, send cherries, and exit.
+    ///
+    /// Exits with 0 on success, 1 if the check failed, and 2 if the check
+    /// succeeded but some cherries failed to send. Suitable for running from
+    /// cron or a systemd timer.
+    RunOnce,
+    /// Interactively choose Bonusly users for reviewers who couldn't be
+    /// matched automatically.
+    ///
+    /// Their pending cherries are sent on the next check.
+    Resolve,
+    /// Send cherries for approvals submitted between two dates, such as
+    /// approvals from before cherries-4-prs was installed.
+    ///
+    /// Prints the bonuses that would be sent and asks for confirmation.
+    /// Reviews that have already been replied to are skipped, and bonuses
+    /// that would exceed the giving balance are left pending. Dates can be
+    /// `YYYY-MM-DD` (midnight UTC) or RFC 3339 timestamps.
+    Backfill {
+        /// Start of the window, inclusive.
+        #[structopt(long, parse(try_from_str = parse_datetime))]
+        since: DateTime<Utc>,
+        /// End of the window, exclusive. Defaults to now.
+        #[structopt(long, parse(try_from_str = parse_datetime))]
+        until: Option<DateTime<Utc>>,
+        /// Don't ask for confirmation.
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Print a summary of the state file.
+    Status {
+        /// Print the summary as JSON.
+        #[structopt(long)]
+        json: bool,
+    },
 }
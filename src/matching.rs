@@ -0,0 +1,207 @@
+//! Fuzzy matching of GitHub users to Bonusly users.
+use std::cmp::Ordering;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+
+use crate::bonusly;
+use crate::github;
+
+/// How many candidates to keep for a GitHub user we can't match confidently.
+pub(crate) const MAX_CANDIDATES: usize = 5;
+
+/// A Bonusly user who might be a given GitHub user.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Candidate {
+    pub email: String,
+    pub full_name: String,
+    /// How confident we are that this is the right user, from 0 to 1.
+    pub confidence: f64,
+}
+
+impl Candidate {
+    pub fn certain(email: String, full_name: String) -> Self {
+        Self {
+            email,
+            full_name,
+            confidence: 1.0,
+        }
+    }
+
+    fn from_user(user: &bonusly::User, confidence: f64) -> Self {
+        Self {
+            email: user.email.clone(),
+            full_name: user.full_name.clone(),
+            confidence,
+        }
+    }
+}
+
+/// The result of looking up a GitHub user's Bonusly email.
+#[derive(Clone, Debug)]
+pub enum BonuslyMatch {
+    /// A single user matched above the confidence threshold.
+    Found(Candidate),
+    /// No user matched confidently. Contains the best candidates, most
+    /// confident first; may be empty.
+    Unresolved(Vec<Candidate>),
+}
+
+/// Rank every Bonusly user against a GitHub user's name and login, most
+/// confident first. Users that don't match at all are omitted.
+///
+/// Names are compared word by word, so "Dan Smith" only half matches
+/// "Jordan Smith"; a word may be shortened, like "Matt" for "Matthew", or
+/// abbreviated, at a much lower confidence. Logins
+/// are compared against the usernames people usually make from their names,
+/// like `dsmith` or `dansmith`.
+pub fn rank_bonusly_users(users: &[bonusly::User], find: &github::User) -> Vec<Candidate> {
+    let name = find.name.as_deref().map(words).unwrap_or_default();
+    let login = normalize(&find.login);
+    let matcher = SkimMatcherV2::default();
+
+    let mut ret: Vec<Candidate> = users
+        .iter()
+        .filter_map(|user| {
+            let confidence = [&user.full_name, &user.display_name]
+                .into_iter()
+                .map(|choice| name_confidence(&matcher, &name, &words(choice)))
+                .chain(std::iter::once(login_confidence(&login, user)))
+                .fold(0.0, f64::max);
+            (confidence > 0.0).then(|| Candidate::from_user(user, confidence))
+        })
+        .collect();
+
+    ret.sort_by(|a, b| {
+        b.confidence
+            .partial_cmp(&a.confidence)
+            .unwrap_or(Ordering::Equal)
+    });
+    ret
+}
+
+/// Pick the best of `candidates` (as returned by [`rank_bonusly_users`]) if
+/// it's above `threshold` and unambiguous.
+pub fn best_match(mut candidates: Vec<Candidate>, threshold: f64) -> BonuslyMatch {
+    let confident = match candidates.as_slice() {
+        [best, second, ..] => best.confidence >= threshold && best.confidence > second.confidence,
+        [best] => best.confidence >= threshold,
+        [] => false,
+    };
+    if confident {
+        BonuslyMatch::Found(candidates.swap_remove(0))
+    } else {
+        candidates.truncate(MAX_CANDIDATES);
+        BonuslyMatch::Unresolved(candidates)
+    }
+}
+
+/// Confidence for a word that's a prefix of the other, like "Matt" and
+/// "Matthew". Deliberately the same for every prefix, so that "Dan" is
+/// ambiguous between "Daniel" and "Danielle".
+const PREFIX_CONFIDENCE: f64 = 0.8;
+
+/// Confidence for a login made from both of a user's names, like `dsmith`.
+const FULL_LOGIN_CONFIDENCE: f64 = 0.9;
+
+/// Confidence for a login that's just one of a user's names; too common to
+/// accept at the default threshold.
+const PARTIAL_LOGIN_CONFIDENCE: f64 = 0.6;
+
+/// Lowercase alphanumeric words in `name`.
+fn words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// `login` lowercased, without punctuation.
+fn normalize(login: &str) -> String {
+    words(login).concat()
+}
+
+/// How well two words match, scored by skim relative to a perfect match of
+/// the shorter word. A prefix scores perfectly and gets
+/// [`PREFIX_CONFIDENCE`]; other matches, like "dnl" for "daniel" or "dan" in
+/// "jordan", get the square of their relative score times that, so they
+/// count for much less.
+fn word_confidence(matcher: &SkimMatcherV2, a: &str, b: &str) -> f64 {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if short == long {
+        return 1.0;
+    }
+    if short.chars().count() < 3 {
+        return 0.0;
+    }
+    match (
+        matcher.fuzzy_match(long, short),
+        matcher.fuzzy_match(short, short),
+    ) {
+        (Some(score), Some(perfect)) if perfect > 0 => {
+            let relative = (score as f64 / perfect as f64).min(1.0);
+            PREFIX_CONFIDENCE * relative * relative
+        }
+        _ => 0.0,
+    }
+}
+
+/// How well two names match, word by word: twice the total confidence of
+/// the best pairing of words, over the total number of words. Each word is
+/// paired at most once.
+fn name_confidence(matcher: &SkimMatcherV2, a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let mut unpaired: Vec<&str> = b.iter().map(String::as_str).collect();
+    let mut total = 0.0;
+    for word in a {
+        let best = unpaired
+            .iter()
+            .enumerate()
+            .map(|(i, other)| (i, word_confidence(matcher, word, other)))
+            .filter(|(_, confidence)| *confidence > 0.0)
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        if let Some((i, confidence)) = best {
+            unpaired.swap_remove(i);
+            total += confidence;
+        }
+    }
+    2.0 * total / (a.len() + b.len()) as f64
+}
+
+/// How well a normalized GitHub `login` matches the usernames `user` might
+/// have made from their name, or their Bonusly username.
+fn login_confidence(login: &str, user: &bonusly::User) -> f64 {
+    if login.is_empty() {
+        return 0.0;
+    }
+    if normalize(&user.short_name) == login {
+        return FULL_LOGIN_CONFIDENCE;
+    }
+    let first = normalize(&user.first_name);
+    let last = normalize(&user.last_name);
+    if first.is_empty() || last.is_empty() {
+        return if login == first || login == last {
+            PARTIAL_LOGIN_CONFIDENCE
+        } else {
+            0.0
+        };
+    }
+    let initial = |name: &str| name.chars().next().map(String::from).unwrap_or_default();
+    let full = [
+        format!("{first}{last}"),
+        format!("{}{last}", initial(&first)),
+        format!("{first}{}", initial(&last)),
+        format!("{last}{first}"),
+        format!("{last}{}", initial(&first)),
+    ];
+    if full.iter().any(|form| form == login) {
+        FULL_LOGIN_CONFIDENCE
+    } else if login == first || login == last {
+        PARTIAL_LOGIN_CONFIDENCE
+    } else {
+        0.0
+    }
+}
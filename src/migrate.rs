@@ -0,0 +1,113 @@
+//! Migrations between versions of the state file format.
+//!
+//! Each migration takes the JSON for one version of the state file and
+//! produces the JSON for the next version. State files written before
+//! versioning was introduced have no `version` field and are treated as
+//! version 0.
+//!
+//! Some migrations need to know the main PR author (`github.user` in the
+//! config), to move state that used to belong to the only author.
+use color_eyre::eyre;
+use serde_json::{json, Map, Value};
+use tracing::info;
+
+/// The version of the state file format written by this version of the
+/// program.
+pub const CURRENT_STATE_VERSION: u64 = 5;
+
+/// A migration from one version to the next. Takes the state and the main
+/// author.
+type Migration = fn(&mut Map<String, Value>, &str);
+
+/// Migrations, indexed by the version they migrate from.
+const MIGRATIONS: [Migration; CURRENT_STATE_VERSION as usize] =
+    [v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5];
+
+/// Upgrade the JSON for a state file of any version to
+/// [`CURRENT_STATE_VERSION`].
+pub fn migrate_state(mut state: Value, main_author: &str) -> eyre::Result<Value> {
+    let object = state
+        .as_object_mut()
+        .ok_or_else(|| eyre::eyre!("State file isn't a JSON object"))?;
+    let mut version = match object.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| eyre::eyre!("State file version isn't an integer: {version}"))?,
+    };
+    if version > CURRENT_STATE_VERSION {
+        return Err(eyre::eyre!(
+            "State file version {version} is newer than the latest supported version \
+             {CURRENT_STATE_VERSION}; was it written by a newer version of cherries-4-prs?"
+        ));
+    }
+
+    while version < CURRENT_STATE_VERSION {
+        info!(
+            from = version,
+            to = version + 1,
+            "Migrating state file format"
+        );
+        MIGRATIONS[version as usize](object, main_author);
+        version += 1;
+        object.insert("version".to_owned(), json!(version));
+    }
+
+    Ok(state)
+}
+
+/// Version 1 adds fuzzy-match candidates and the mappings learned with the
+/// `resolve` command.
+fn v0_to_v1(state: &mut Map<String, Value>, _main_author: &str) {
+    for key in ["unmatched_candidates", "learned_emails"] {
+        state.entry(key).or_insert_with(|| json!({}));
+    }
+    state
+        .entry("ignored_reviewers")
+        .or_insert_with(|| json!([]));
+}
+
+/// Version 2 adds per-month approval counts for spending down the balance.
+fn v1_to_v2(state: &mut Map<String, Value>, _main_author: &str) {
+    state.entry("monthly_approvals").or_insert_with(|| {
+        json!({
+            "month": "",
+            "approvals": {},
+            "spent_down": false,
+        })
+    });
+}
+
+/// Version 3 adds the position for the round-robin hashtag policy.
+fn v2_to_v3(state: &mut Map<String, Value>, _main_author: &str) {
+    state
+        .entry("hashtag_round_robin")
+        .or_insert_with(|| json!(0));
+}
+
+/// Version 4 moves replied/pending reviews and monthly approvals into a
+/// per-author section, so that several authors can share a state file.
+fn v3_to_v4(state: &mut Map<String, Value>, main_author: &str) {
+    let mut author = Map::new();
+    for key in ["replied_prs", "non_replied_prs", "monthly_approvals"] {
+        if let Some(value) = state.remove(key) {
+            author.insert(key.to_owned(), value);
+        }
+    }
+    let authors = state.entry("authors").or_insert_with(|| json!({}));
+    if let Some(authors) = authors.as_object_mut() {
+        authors.insert(main_author.to_owned(), Value::Object(author));
+    }
+}
+
+/// Version 5 adds each author's bonuses in flight; see
+/// [`InFlightBonus`](crate::InFlightBonus).
+fn v4_to_v5(state: &mut Map<String, Value>, _main_author: &str) {
+    if let Some(authors) = state.get_mut("authors").and_then(Value::as_object_mut) {
+        for author in authors.values_mut() {
+            if let Some(author) = author.as_object_mut() {
+                author.entry("in_flight").or_insert_with(|| json!([]));
+            }
+        }
+    }
+}
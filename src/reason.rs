@@ -0,0 +1,252 @@
+//! Templates for the reason message sent with each bonus.
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use color_eyre::eyre;
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::condition::Condition;
+use crate::github;
+
+/// The `[reasons]` config section.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Config {
+    /// Templates to pick from at random, unless a rule matches.
+    #[serde(default = "templates_default")]
+    pub templates: Vec<Template>,
+    /// The first matching rule's templates are used instead of `templates`.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            templates: templates_default(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+fn templates_default() -> Vec<Template> {
+    vec![Template::try_from("thanks for approving my PR! {review_url}".to_owned()).unwrap()]
+}
+
+/// Templates to use for PRs matching all of the rule's conditions.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(flatten)]
+    pub condition: Condition,
+    pub templates: Vec<Template>,
+}
+
+/// Values to fill a [`Template`] in with.
+#[derive(Clone, Debug)]
+pub struct Context<'a> {
+    pub pr: &'a github::PullRequest,
+    pub details: Option<&'a github::PullRequestDetails>,
+    /// The reviewer's name, or their GitHub username if they haven't set one.
+    pub reviewer_name: &'a str,
+    pub review_url: &'a str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Placeholder {
+    ReviewerName,
+    PrTitle,
+    Repo,
+    PrNumber,
+    ReviewUrl,
+    LinesChanged,
+}
+
+impl Placeholder {
+    const ALL: [(&'static str, Placeholder); 6] = [
+        ("reviewer_name", Placeholder::ReviewerName),
+        ("pr_title", Placeholder::PrTitle),
+        ("repo", Placeholder::Repo),
+        ("pr_number", Placeholder::PrNumber),
+        ("review_url", Placeholder::ReviewUrl),
+        ("lines_changed", Placeholder::LinesChanged),
+    ];
+
+    fn needs_details(self) -> bool {
+        matches!(self, Placeholder::PrTitle | Placeholder::LinesChanged)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A reason message with `{placeholders}`, like
+/// `"thanks for reviewing {pr_title}! {review_url}"`. Use `{{` and `}}` for
+/// literal braces.
+///
+/// Placeholders are checked when the template is parsed.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "String")]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl TryFrom<String> for Template {
+    type Error = eyre::Error;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    if chars.as_str().starts_with('{') {
+                        chars.next();
+                        literal.push('{');
+                        continue;
+                    }
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or_else(|| {
+                        eyre::eyre!("Unclosed `{{` in reason template {source:?}")
+                    })?;
+                    let name = &rest[..end];
+                    let placeholder = Placeholder::ALL
+                        .iter()
+                        .find(|(known, _)| *known == name)
+                        .map(|(_, placeholder)| *placeholder)
+                        .ok_or_else(|| {
+                            eyre::eyre!(
+                                "Unknown placeholder `{{{name}}}` in reason template {source:?}; \
+                                 expected one of {}",
+                                Placeholder::ALL
+                                    .iter()
+                                    .map(|(known, _)| format!("`{{{known}}}`"))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )
+                        })?;
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Placeholder(placeholder));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => {
+                    if chars.as_str().starts_with('}') {
+                        chars.next();
+                        literal.push('}');
+                    } else {
+                        return Err(eyre::eyre!(
+                            "Unmatched `}}` in reason template {source:?}; use `}}}}` for a literal brace"
+                        ));
+                    }
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    fn needs_details(&self) -> bool {
+        self.parts.iter().any(|part| match part {
+            Part::Placeholder(placeholder) => placeholder.needs_details(),
+            Part::Literal(_) => false,
+        })
+    }
+
+    pub fn render(&self, context: &Context<'_>) -> String {
+        let mut ret = String::new();
+        for part in &self.parts {
+            // Writing to a `String` can't fail.
+            let _ = match part {
+                Part::Literal(literal) => write!(ret, "{literal}"),
+                Part::Placeholder(placeholder) => match placeholder {
+                    Placeholder::ReviewerName => write!(ret, "{}", context.reviewer_name),
+                    Placeholder::PrTitle => write!(
+                        ret,
+                        "{}",
+                        context
+                            .details
+                            .map(|details| details.title.as_str())
+                            .unwrap_or_default()
+                    ),
+                    Placeholder::Repo => {
+                        write!(ret, "{}/{}", context.pr.org, context.pr.repo)
+                    }
+                    Placeholder::PrNumber => write!(ret, "{}", context.pr.number),
+                    Placeholder::ReviewUrl => write!(ret, "{}", context.review_url),
+                    Placeholder::LinesChanged => write!(
+                        ret,
+                        "{}",
+                        context
+                            .details
+                            .map(|details| details.lines_changed())
+                            .unwrap_or_default()
+                    ),
+                },
+            };
+        }
+        ret
+    }
+}
+
+impl Rule {
+    fn matches(&self, context: &Context<'_>) -> bool {
+        self.condition.matches(context.pr, context.details)
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.templates.is_empty() {
+            return Err(eyre::eyre!("reasons.templates is empty"));
+        }
+        if self.rules.iter().any(|rule| rule.templates.is_empty()) {
+            return Err(eyre::eyre!("A rule in reasons.rules has no templates"));
+        }
+        Ok(())
+    }
+
+    /// Whether rendering a reason needs PR details from GitHub.
+    pub fn needs_details(&self) -> bool {
+        self.templates
+            .iter()
+            .chain(self.rules.iter().flat_map(|rule| &rule.templates))
+            .any(Template::needs_details)
+            || self.rules.iter().any(|rule| rule.condition.needs_details())
+    }
+
+    /// Pick a template and render it.
+    ///
+    /// The review URL is appended if the template doesn't include it; it's
+    /// what tells this bonus apart from others to the same reviewer when
+    /// checking whether it was sent. See
+    /// [`Program::reconcile_in_flight`](crate::Program::reconcile_in_flight).
+    pub fn reason(&self, context: &Context<'_>) -> String {
+        let templates = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(context))
+            .map_or(&self.templates, |rule| &rule.templates);
+        let mut reason = templates
+            .choose(&mut rand::thread_rng())
+            .map(|template| template.render(context))
+            .unwrap_or_default();
+        if !reason.contains(context.review_url) {
+            if !reason.is_empty() {
+                reason.push(' ');
+            }
+            reason.push_str(context.review_url);
+        }
+        reason
+    }
+}
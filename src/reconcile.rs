@@ -0,0 +1,211 @@
+//! Making sure a bonus is never sent twice, even if the program dies between
+//! sending it and recording that it was sent.
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::bonusly;
+use crate::github;
+use crate::{AuthorState, Program, ReviewSource, RewardSink};
+
+/// How far Bonusly's clock may be behind ours. `created_at` comes from
+/// Bonusly, but `InFlightBonus::started` comes from us.
+const CLOCK_SKEW_MINUTES: i64 = 10;
+
+/// A bonus that was about to be sent, and the reviews it's for.
+///
+/// The bonus's reason includes the URL of each review (see
+/// [`reason::Config::reason`](crate::reason::Config::reason)), so it can be
+/// told apart from other bonuses to the same receiver. Spend-down bonuses
+/// have no reviews.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InFlightBonus {
+    pub reviews: Vec<github::NonRepliedReview>,
+    pub bonus: bonusly::Bonus,
+    /// When we started sending it.
+    pub started: DateTime<Utc>,
+}
+
+impl AuthorState {
+    /// Whether a bonus for `reviews` is in flight.
+    pub(crate) fn is_in_flight(&self, reviews: &[github::NonRepliedReview]) -> bool {
+        self.in_flight
+            .iter()
+            .any(|in_flight| in_flight.reviews == reviews)
+    }
+
+    /// Forget the in-flight `bonus` for `reviews`, once we know whether it
+    /// was sent.
+    pub(crate) fn finish_in_flight(
+        &mut self,
+        reviews: &[github::NonRepliedReview],
+        bonus: &bonusly::Bonus,
+    ) {
+        self.in_flight
+            .retain(|in_flight| in_flight.reviews != reviews || in_flight.bonus != *bonus);
+    }
+}
+
+impl<S: ReviewSource, R: RewardSink> Program<S, R> {
+    /// Send `bonus` for `reviews` (none for spend-down bonuses) and mark
+    /// them replied to, then sleep for `interval`.
+    ///
+    /// The bonus is recorded in flight first. If Bonusly rejects it, the
+    /// reviews are left pending to be retried; if the outcome is unknown, the
+    /// bonus is left in flight to be reconciled.
+    pub(crate) async fn send_bonus(
+        &mut self,
+        author: &str,
+        reviews: Vec<github::NonRepliedReview>,
+        bonus: &bonusly::Bonus,
+        interval: Duration,
+    ) -> eyre::Result<()> {
+        if self.dry_run {
+            info!(
+                reviewers = ?reviews.iter().map(|review| &review.reviewer).collect::<Vec<_>>(),
+                receiver_email = %bonus.receiver_email,
+                amount = bonus.amount,
+                hashtag = %bonus.hashtag,
+                reason = %bonus.reason,
+                "Dry run; would send cherries"
+            );
+            return Ok(());
+        }
+        self.start_in_flight(author, reviews.clone(), bonus).await?;
+
+        let result = self
+            .credentials
+            .bonusly_for(&self.config, author)?
+            .send_bonus(bonus)
+            .await;
+        tokio::time::sleep(interval).await;
+        match result {
+            Ok(reply) => {
+                info!(?reply, "Sent cherries");
+                self.spend(author, bonus.amount);
+                self.state
+                    .author_mut(author)
+                    .finish_in_flight(&reviews, bonus);
+                self.mark_sent(author, reviews, bonus);
+                self.write_state().await
+            }
+            Err(err)
+                if err
+                    .downcast_ref::<bonusly::ApiError>()
+                    .is_some_and(bonusly::ApiError::is_rejection) =>
+            {
+                // Bonusly refused the bonus, so it definitely wasn't sent.
+                info!(?err, "Failed to send bonus");
+                let author_state = self.state.author_mut(author);
+                author_state.finish_in_flight(&reviews, bonus);
+                author_state.non_replied_prs.extend(reviews);
+                self.write_state().await?;
+                Err(err)
+            }
+            Err(err) => {
+                // The request may or may not have been processed, e.g. a
+                // timeout or a gateway error; leave the bonus in flight to be
+                // reconciled.
+                warn!(?err, "Failed to send bonus; it may have been sent anyway");
+                Err(err)
+            }
+        }
+    }
+
+    /// Record that `bonus` for `reviews` is about to be sent, and write the
+    /// state file so the record survives a crash.
+    async fn start_in_flight(
+        &mut self,
+        author: &str,
+        reviews: Vec<github::NonRepliedReview>,
+        bonus: &bonusly::Bonus,
+    ) -> eyre::Result<()> {
+        self.state.author_mut(author).in_flight.push(InFlightBonus {
+            reviews,
+            bonus: bonus.clone(),
+            started: Utc::now(),
+        });
+        self.write_state().await
+    }
+
+    /// Record that `bonus` was sent for `reviews`.
+    fn mark_sent(
+        &mut self,
+        author: &str,
+        reviews: Vec<github::NonRepliedReview>,
+        bonus: &bonusly::Bonus,
+    ) {
+        let author_state = self.state.author_mut(author);
+        for review in reviews {
+            author_state
+                .monthly_approvals
+                .record(&bonus.receiver_email, Utc::now());
+            author_state.replied_prs.insert(review.into());
+        }
+    }
+
+    /// Check whether `author`'s bonuses in flight were sent, by looking for
+    /// them in the bonuses Bonusly has. Those that were are marked sent, and
+    /// the rest are left pending to be sent again.
+    #[instrument(skip(self), level = "debug")]
+    pub(crate) async fn reconcile_in_flight(&mut self, author: &str) -> eyre::Result<()> {
+        let in_flight = self.state.author(author).in_flight.clone();
+        let skew = chrono::Duration::minutes(CLOCK_SKEW_MINUTES);
+        let since = match in_flight.iter().map(|in_flight| in_flight.started).min() {
+            Some(started) => started - skew,
+            None => return Ok(()),
+        };
+        info!(
+            count = in_flight.len(),
+            "Checking whether bonuses in flight were sent"
+        );
+        let mut sent = self
+            .credentials
+            .bonusly_for(&self.config, author)?
+            .sent_bonuses(since)
+            .await?;
+
+        for InFlightBonus {
+            reviews,
+            bonus,
+            started,
+        } in in_flight
+        {
+            self.state
+                .author_mut(author)
+                .finish_in_flight(&reviews, &bonus);
+            // Each bonus Bonusly has accounts for at most one in flight. The
+            // reason includes the review URLs, so the time is only a sanity
+            // check, and allows for skew.
+            match sent
+                .iter()
+                .position(|sent| sent.matches(&bonus, started - skew))
+            {
+                Some(index) => {
+                    sent.swap_remove(index);
+                    info!(?reviews, ?bonus, "Bonus in flight was sent");
+                    self.mark_sent(author, reviews, &bonus);
+                }
+                None if reviews.is_empty() => {
+                    // Spending down is only tried once a month; see
+                    // `Program::maybe_spend_down`.
+                    warn!(
+                        ?bonus,
+                        "Spend-down bonus in flight wasn't sent; not retrying"
+                    );
+                }
+                None => {
+                    warn!(?reviews, ?bonus, "Bonus in flight wasn't sent; will retry");
+                    self.state
+                        .author_mut(author)
+                        .non_replied_prs
+                        .extend(reviews);
+                }
+            }
+        }
+        self.write_state().await
+    }
+}
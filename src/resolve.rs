@@ -0,0 +1,118 @@
+//! Resolving reviewers we couldn't match to a Bonusly user.
+use std::collections::BTreeMap;
+
+use color_eyre::eyre;
+use tracing::{info, instrument};
+
+use crate::github;
+use crate::matching::{rank_bonusly_users, Candidate, MAX_CANDIDATES};
+use crate::{Program, ReviewSource, RewardSink};
+
+/// A GitHub reviewer with reviews we haven't sent cherries for because we
+/// couldn't find their Bonusly email.
+#[derive(Clone, Debug)]
+pub struct UnresolvedReviewer {
+    /// GitHub username.
+    pub login: String,
+    /// Full name from the reviewer's GitHub profile, if known.
+    pub name: Option<String>,
+    pub reviews: Vec<github::NonRepliedReview>,
+    /// Possible Bonusly users, most likely first.
+    pub candidates: Vec<Candidate>,
+}
+
+/// What to do with an [`UnresolvedReviewer`].
+#[derive(Clone, Debug)]
+pub enum Resolution {
+    /// Send cherries to this Bonusly email from now on.
+    Email(String),
+    /// Never send cherries to this reviewer, and forget their pending reviews.
+    Ignore,
+}
+
+impl<S: ReviewSource, R: RewardSink> Program<S, R> {
+    /// Reviewers with pending reviews in the state file, sorted by GitHub
+    /// username.
+    pub fn unresolved_reviewers(&self) -> Vec<UnresolvedReviewer> {
+        let mut reviews: BTreeMap<&str, Vec<github::NonRepliedReview>> = BTreeMap::new();
+        for review in self
+            .state
+            .authors
+            .values()
+            .flat_map(|author| &author.non_replied_prs)
+        {
+            reviews
+                .entry(review.reviewer.as_str())
+                .or_default()
+                .push(review.clone());
+        }
+
+        reviews
+            .into_iter()
+            .map(|(login, mut reviews)| {
+                reviews.sort_by(|a, b| {
+                    (&a.pr.org, &a.pr.repo, a.pr.number).cmp(&(&b.pr.org, &b.pr.repo, b.pr.number))
+                });
+                let user = self.state.github_members.get(login);
+                let candidates = match self.state.unmatched_candidates.get(login) {
+                    Some(candidates) if !candidates.is_empty() => candidates.clone(),
+                    _ => user
+                        .map(|user| {
+                            let mut candidates =
+                                rank_bonusly_users(&self.state.bonusly_users, user);
+                            candidates.truncate(MAX_CANDIDATES);
+                            candidates
+                        })
+                        .unwrap_or_default(),
+                };
+                UnresolvedReviewer {
+                    login: login.to_owned(),
+                    name: user.and_then(|user| user.name.clone()),
+                    reviews,
+                    candidates,
+                }
+            })
+            .collect()
+    }
+
+    /// Record how to handle a reviewer's reviews and write the state file.
+    ///
+    /// Pending reviews for a reviewer resolved to an email are sent on the
+    /// next check.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn resolve_reviewer(
+        &mut self,
+        login: &str,
+        resolution: Resolution,
+    ) -> eyre::Result<()> {
+        match resolution {
+            Resolution::Email(email) => {
+                let known = self
+                    .state
+                    .bonusly_users
+                    .iter()
+                    .any(|user| user.can_receive && user.email.eq_ignore_ascii_case(&email));
+                if !known {
+                    return Err(eyre::eyre!(
+                        "{email} isn't a Bonusly user who can receive bonuses"
+                    ));
+                }
+                info!(%login, %email, "Learned Bonusly email for reviewer");
+                self.state.ignored_reviewers.remove(login);
+                self.state.learned_emails.insert(login.to_owned(), email);
+            }
+            Resolution::Ignore => {
+                info!(%login, "Ignoring reviewer");
+                for author in self.state.authors.values_mut() {
+                    author
+                        .non_replied_prs
+                        .retain(|review| review.reviewer != login);
+                }
+                self.state.learned_emails.remove(login);
+                self.state.ignored_reviewers.insert(login.to_owned());
+            }
+        }
+        self.state.unmatched_candidates.remove(login);
+        self.write_state().await
+    }
+}
@@ -0,0 +1,147 @@
+//! Summaries of the program state.
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{Program, State};
+
+/// Why a review hasn't had cherries sent for it yet.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingReason {
+    /// No Bonusly user resembles the reviewer.
+    NoMatch,
+    /// Several Bonusly users resemble the reviewer, or the best one isn't
+    /// close enough.
+    AmbiguousMatch,
+    /// The reviewer has an email, but cherries haven't been sent yet, e.g.
+    /// because sending failed or the reviewer was just resolved.
+    Unsent,
+}
+
+impl Display for PendingReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PendingReason::NoMatch => write!(f, "no Bonusly match"),
+            PendingReason::AmbiguousMatch => write!(f, "ambiguous Bonusly match"),
+            PendingReason::Unsent => write!(f, "not sent yet"),
+        }
+    }
+}
+
+/// Review counts for a single PR author.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthorStatus {
+    pub replied_reviews: usize,
+    pub pending_reviews: usize,
+}
+
+/// A summary of the state file. See [`State::status`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Status {
+    /// Only PRs updated after this are checked.
+    pub cutoff: DateTime<Utc>,
+    /// When Bonusly users and hashtags were last refreshed.
+    pub last_update: DateTime<Utc>,
+    pub replied_reviews: usize,
+    pub pending_reviews: usize,
+    pub pending_reviews_by_reason: BTreeMap<PendingReason, usize>,
+    /// Review counts by PR author.
+    pub authors: BTreeMap<String, AuthorStatus>,
+    pub ignored_reviewers: usize,
+    pub learned_emails: usize,
+    pub github_users: usize,
+    pub bonusly_users: usize,
+    pub hashtags: Vec<String>,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Last check cutoff:       {}", self.cutoff.to_rfc3339())?;
+        writeln!(
+            f,
+            "Last Bonusly refresh:    {}",
+            self.last_update.to_rfc3339()
+        )?;
+        writeln!(f, "Replied reviews:         {}", self.replied_reviews)?;
+        writeln!(f, "Pending reviews:         {}", self.pending_reviews)?;
+        for (reason, count) in &self.pending_reviews_by_reason {
+            writeln!(f, "  {reason}: {count}")?;
+        }
+        if self.authors.len() > 1 {
+            writeln!(f, "By author:")?;
+            for (author, status) in &self.authors {
+                writeln!(
+                    f,
+                    "  {author}: {} replied, {} pending",
+                    status.replied_reviews, status.pending_reviews
+                )?;
+            }
+        }
+        writeln!(f, "Ignored reviewers:       {}", self.ignored_reviewers)?;
+        writeln!(f, "Learned emails:          {}", self.learned_emails)?;
+        writeln!(f, "Cached GitHub users:     {}", self.github_users)?;
+        writeln!(f, "Cached Bonusly users:    {}", self.bonusly_users)?;
+        write!(f, "Hashtags:                {}", self.hashtags.join(" "))
+    }
+}
+
+impl<S, R> Program<S, R> {
+    /// Summarize the program state.
+    pub fn status(&self) -> Status {
+        self.state.status()
+    }
+}
+
+impl State {
+    /// Summarize the program state.
+    pub fn status(&self) -> Status {
+        let state = self;
+        let mut pending_reviews_by_reason = BTreeMap::new();
+        for review in state
+            .authors
+            .values()
+            .flat_map(|author| &author.non_replied_prs)
+        {
+            let reason = match state.unmatched_candidates.get(&review.reviewer) {
+                Some(candidates) if candidates.is_empty() => PendingReason::NoMatch,
+                Some(_) => PendingReason::AmbiguousMatch,
+                None => PendingReason::Unsent,
+            };
+            *pending_reviews_by_reason.entry(reason).or_default() += 1;
+        }
+
+        let mut hashtags = state.hashtags.clone();
+        hashtags.sort();
+
+        let authors: BTreeMap<String, AuthorStatus> = state
+            .authors
+            .iter()
+            .map(|(author, author_state)| {
+                (
+                    author.clone(),
+                    AuthorStatus {
+                        replied_reviews: author_state.replied_prs.len(),
+                        pending_reviews: author_state.non_replied_prs.len(),
+                    },
+                )
+            })
+            .collect();
+
+        Status {
+            cutoff: state.cutoff,
+            last_update: state.last_update,
+            replied_reviews: authors.values().map(|author| author.replied_reviews).sum(),
+            pending_reviews: authors.values().map(|author| author.pending_reviews).sum(),
+            pending_reviews_by_reason,
+            authors,
+            ignored_reviewers: state.ignored_reviewers.len(),
+            learned_emails: state.learned_emails.len(),
+            github_users: state.github_members.len(),
+            bonusly_users: state.bonusly_users.len(),
+            hashtags,
+        }
+    }
+}
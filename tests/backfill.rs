@@ -0,0 +1,54 @@
+use chrono::{Duration, TimeZone, Utc};
+use pretty_assertions::assert_eq;
+
+use cherries_4_prs::{parse_datetime, Backfill};
+
+#[test]
+fn window_includes_start_and_excludes_end() {
+    let since = Utc.ymd(2022, 3, 1).and_hms(0, 0, 0);
+    let until = Utc.ymd(2022, 4, 1).and_hms(0, 0, 0);
+    let backfill = Backfill::new(since, Some(until)).unwrap();
+    assert!(backfill.contains(Some(since)));
+    assert!(backfill.contains(Some(until - Duration::seconds(1))));
+    assert!(!backfill.contains(Some(until)));
+    assert!(!backfill.contains(Some(since - Duration::seconds(1))));
+    // Pending reviews have no submission time.
+    assert!(!backfill.contains(None));
+}
+
+#[test]
+fn empty_window_is_rejected() {
+    let since = Utc.ymd(2022, 3, 1).and_hms(0, 0, 0);
+    assert!(Backfill::new(since, Some(since)).is_err());
+    assert!(Backfill::new(since, Some(since - Duration::days(1))).is_err());
+}
+
+#[test]
+fn dates_and_timestamps_are_parsed() {
+    assert_eq!(
+        parse_datetime("2022-03-01").unwrap(),
+        Utc.ymd(2022, 3, 1).and_hms(0, 0, 0)
+    );
+    assert_eq!(
+        parse_datetime("2022-03-01T09:30:00Z").unwrap(),
+        Utc.ymd(2022, 3, 1).and_hms(9, 30, 0)
+    );
+    assert_eq!(
+        parse_datetime("2022-03-01T09:30:00-05:00").unwrap(),
+        Utc.ymd(2022, 3, 1).and_hms(14, 30, 0)
+    );
+}
+
+#[test]
+fn invalid_datetimes_are_rejected() {
+    for invalid in [
+        "",
+        "yesterday",
+        "2022-13-01",
+        "03/01/2022",
+        "2022-03-01 09:30",
+    ] {
+        let err = parse_datetime(invalid).unwrap_err().to_string();
+        assert!(err.contains("Expected a date like"), "{err}");
+    }
+}
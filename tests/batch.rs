@@ -0,0 +1,83 @@
+use pretty_assertions::assert_eq;
+
+use cherries_4_prs::{batch_reviews, bonusly, github, ReviewStatus};
+
+fn review(number: i64, reviewer: &str, id: u64) -> github::NonRepliedReview {
+    github::NonRepliedReview {
+        pr: github::PullRequest {
+            org: "acme".to_owned(),
+            repo: "widgets".to_owned(),
+            number,
+        },
+        reviewer: reviewer.to_owned(),
+        id: github::ReviewId(id),
+    }
+}
+
+fn ok(number: i64, reviewer: &str, id: u64, amount: usize) -> ReviewStatus {
+    let url = format!("https://github.com/acme/widgets/pull/{number}#pullrequestreview-{id}");
+    ReviewStatus::Ok(
+        review(number, reviewer, id),
+        bonusly::Bonus {
+            receiver_email: format!("{reviewer}@example.com"),
+            amount,
+            hashtag: "#teamwork".to_owned(),
+            reason: format!("thanks! {url}"),
+        },
+        url,
+    )
+}
+
+fn batch(batched: &[ReviewStatus]) -> (Vec<github::NonRepliedReview>, bonusly::Bonus) {
+    match batched {
+        [ReviewStatus::Batch(reviews, bonus)] => (reviews.clone(), bonus.clone()),
+        other => panic!("Expected a single batch, got {other:?}"),
+    }
+}
+
+#[test]
+fn amounts_are_summed() {
+    let (reviews, bonus) = batch(&batch_reviews(vec![
+        ok(1, "mona", 1, 2),
+        ok(2, "mona", 2, 3),
+    ]));
+    assert_eq!(reviews, vec![review(1, "mona", 1), review(2, "mona", 2)]);
+    assert_eq!(bonus.receiver_email, "mona@example.com");
+    assert_eq!(bonus.amount, 5);
+    assert_eq!(
+        bonus.reason,
+        "thanks for approving 2 of my PRs! \
+         https://github.com/acme/widgets/pull/1#pullrequestreview-1 \
+         https://github.com/acme/widgets/pull/2#pullrequestreview-2"
+    );
+}
+
+#[test]
+fn pr_approved_twice_is_counted_once() {
+    let (reviews, bonus) = batch(&batch_reviews(vec![
+        ok(1, "mona", 1, 2),
+        ok(1, "mona", 2, 2),
+        ok(2, "mona", 3, 2),
+    ]));
+    assert_eq!(reviews, vec![review(1, "mona", 1), review(2, "mona", 3)]);
+    assert_eq!(bonus.amount, 4);
+}
+
+#[test]
+fn single_review_is_left_alone() {
+    let batched = batch_reviews(vec![
+        ok(1, "mona", 1, 2),
+        ok(2, "hubot", 2, 2),
+        ok(3, "mona", 3, 2),
+    ]);
+    assert_eq!(batched.len(), 2);
+    match &batched[..] {
+        [ReviewStatus::Ok(review, bonus, _), ReviewStatus::Batch(reviews, _)]
+        | [ReviewStatus::Batch(reviews, _), ReviewStatus::Ok(review, bonus, _)] => {
+            assert_eq!(review.reviewer, "hubot");
+            assert_eq!(bonus.amount, 2);
+            assert_eq!(reviews.len(), 2);
+        }
+        other => panic!("Expected one batch and one review, got {other:?}"),
+    }
+}
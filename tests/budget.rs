@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use chrono::{TimeZone, Utc};
+use pretty_assertions::assert_eq;
+
+use cherries_4_prs::{proportional_shares, MonthlyApprovals};
+
+fn approvals(counts: &[(&str, usize)]) -> BTreeMap<String, usize> {
+    counts
+        .iter()
+        .map(|(email, count)| ((*email).to_owned(), *count))
+        .collect()
+}
+
+fn shares(balance: usize, counts: &[(&str, usize)]) -> Vec<(String, usize)> {
+    proportional_shares(balance, &approvals(counts))
+}
+
+fn expected(shares: &[(&str, usize)]) -> Vec<(String, usize)> {
+    shares
+        .iter()
+        .map(|(email, share)| ((*email).to_owned(), *share))
+        .collect()
+}
+
+#[test]
+fn shares_divide_evenly() {
+    assert_eq!(
+        shares(7, &[("a", 5), ("b", 2)]),
+        expected(&[("a", 5), ("b", 2)])
+    );
+}
+
+#[test]
+fn leftover_goes_to_largest_remainders() {
+    // 3.75 and 1.25.
+    assert_eq!(
+        shares(5, &[("a", 3), ("b", 1)]),
+        expected(&[("a", 4), ("b", 1)])
+    );
+    // Ties are broken in order.
+    assert_eq!(
+        shares(10, &[("a", 1), ("b", 1), ("c", 1)]),
+        expected(&[("a", 4), ("b", 3), ("c", 3)])
+    );
+}
+
+#[test]
+fn zero_shares_are_omitted() {
+    assert_eq!(
+        shares(2, &[("a", 1), ("b", 1), ("c", 1)]),
+        expected(&[("a", 1), ("b", 1)])
+    );
+}
+
+#[test]
+fn shares_add_up_to_the_balance() {
+    let counts = [("a", 7), ("b", 3), ("c", 11), ("d", 1)];
+    for balance in 0..50 {
+        let total: usize = shares(balance, &counts)
+            .iter()
+            .map(|(_, share)| share)
+            .sum();
+        assert_eq!(total, balance);
+    }
+}
+
+#[test]
+fn nothing_to_share() {
+    assert_eq!(shares(0, &[("a", 1)]), vec![]);
+    assert_eq!(shares(10, &[]), vec![]);
+    assert_eq!(shares(10, &[("a", 0)]), vec![]);
+}
+
+#[test]
+fn approvals_roll_over_each_month() {
+    let mut monthly = MonthlyApprovals::default();
+    monthly.record("a", Utc.ymd(2022, 3, 30).and_hms(12, 0, 0));
+    monthly.record("a", Utc.ymd(2022, 3, 31).and_hms(12, 0, 0));
+    monthly.spent_down = true;
+    assert_eq!(monthly.approvals["a"], 2);
+
+    monthly.record("b", Utc.ymd(2022, 4, 1).and_hms(0, 0, 0));
+    assert_eq!(monthly.month, "2022-04");
+    assert_eq!(monthly.approvals, approvals(&[("b", 1)]));
+    assert!(!monthly.spent_down);
+}
@@ -0,0 +1,62 @@
+//! Temporary directories for config and state files.
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cherries_4_prs::Config;
+
+/// A fresh directory for one test's files, removed when dropped.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// An empty directory for the test `name`, which must be unique within
+    /// the test binary.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("cherries-4-prs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.path.join("config.toml")
+    }
+
+    /// Write a config file for `me`, who has PRs in `acme`, and read it
+    /// back. `extra` is added at the top level and `github` to the `[github]`
+    /// table.
+    pub fn config(&self, extra: &str, github: &str) -> Config {
+        fs::write(
+            self.config_path(),
+            format!(
+                r#"
+                cherries_per_check = 2
+                send_bonus_delay_seconds = 0
+                data_path = "state.json"
+                {extra}
+
+                [github]
+                user = "me"
+                org = "acme"
+                {github}
+                "#
+            ),
+        )
+        .unwrap();
+        Config::from_path(self.config_path()).unwrap()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,61 @@
+use cherries_4_prs::condition::Condition;
+use cherries_4_prs::{amount, github};
+
+fn pr() -> github::PullRequest {
+    github::PullRequest {
+        org: "acme".to_owned(),
+        repo: "widgets".to_owned(),
+        number: 1,
+    }
+}
+
+fn details(labels: &[&str]) -> github::PullRequestDetails {
+    github::PullRequestDetails {
+        title: "Frobnicate the widgets".to_owned(),
+        html_url: "https://github.com/acme/widgets/pull/1".to_owned(),
+        additions: 0,
+        deletions: 0,
+        changed_files: 0,
+        labels: labels
+            .iter()
+            .map(|name| github::Label {
+                name: (*name).to_owned(),
+            })
+            .collect(),
+    }
+}
+
+fn condition(toml: &str) -> Condition {
+    toml::de::from_str(toml).unwrap()
+}
+
+#[test]
+fn repo_matches_by_name_or_owner() {
+    assert!(condition(r#"repo = "widgets""#).matches(&pr(), None));
+    assert!(condition(r#"repo = "acme/widgets""#).matches(&pr(), None));
+    assert!(!condition(r#"repo = "other/widgets""#).matches(&pr(), None));
+    assert!(condition("").matches(&pr(), None));
+}
+
+#[test]
+fn label_needs_details() {
+    let hotfix = condition(r#"label = "hotfix""#);
+    assert!(hotfix.needs_details());
+    assert!(hotfix.matches(&pr(), Some(&details(&["bug", "hotfix"]))));
+    assert!(!hotfix.matches(&pr(), Some(&details(&["bug"]))));
+    assert!(!hotfix.matches(&pr(), None));
+}
+
+#[test]
+fn rules_reject_unknown_fields() {
+    let err = toml::de::from_str::<amount::Rule>(
+        r#"
+        repo = "acme/widgets"
+        labels = ["hotfix"]
+        add = 1
+        "#,
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(err.contains("unknown field `labels`"), "{err}");
+}
@@ -0,0 +1,607 @@
+//! Runs `Program` against local stand-ins for the GitHub and Bonusly APIs.
+mod common;
+mod mock_servers;
+
+use std::fs;
+
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+
+use cherries_4_prs::{bonusly, github, Config, Credentials, Program, Resolution, SendErrors};
+use common::TestDir;
+use mock_servers::{MockBonusly, MockGitHub};
+
+struct Harness {
+    github: MockGitHub,
+    bonusly: MockBonusly,
+    dir: TestDir,
+}
+
+impl Harness {
+    /// Mock servers with `me` (the PR author) and no reviewers yet, and a
+    /// fresh directory for the config and state files.
+    fn new(name: &str) -> Self {
+        Self::with_config(name, "")
+    }
+
+    /// [`Harness::new`], with `extra` top-level config.
+    fn with_config(name: &str, extra: &str) -> Self {
+        let dir = TestDir::new(name);
+        let github = MockGitHub::start();
+        github.add_user("me", Some("Me Myself"));
+        let bonusly = MockBonusly::start("me@example.com");
+        dir.config(
+            &format!("credentials_path = \"credentials.toml\"\n{extra}"),
+            &format!("api_url = \"{}\"", github.server.url),
+        );
+        fs::write(
+            dir.path().join("credentials.toml"),
+            "bonusly = \"bonusly-token\"\ngithub = \"github-token\"\n",
+        )
+        .unwrap();
+        Self {
+            github,
+            bonusly,
+            dir,
+        }
+    }
+
+    /// Start the program, reading the state file if it exists.
+    async fn program(&self) -> Program {
+        self.try_program().await.unwrap()
+    }
+
+    /// [`Harness::program`], returning any error.
+    async fn try_program(&self) -> color_eyre::eyre::Result<Program> {
+        let config = Config::from_path(self.dir.config_path()).unwrap();
+        let mut credentials = Credentials::from_config(&config).unwrap();
+        credentials.bonusly = bonusly::Client::builder("bonusly-token".to_owned())
+            .base_url(&self.bonusly.server.url)
+            .build();
+        Program::new(config, credentials, false).await
+    }
+
+    /// The state file's contents.
+    fn state(&self) -> Value {
+        serde_json::from_str(&fs::read_to_string(self.dir.path().join("state.json")).unwrap())
+            .unwrap()
+    }
+
+    /// Record a bonus to `receiver_email` for `review` as in flight in the
+    /// state file, as if the program died while sending it.
+    fn set_in_flight(&self, review: u64, receiver_email: &str, reason: &str, started: &str) {
+        let mut state = self.state();
+        let author = &mut state["authors"]["me"];
+        if author.is_null() {
+            *author = json!({
+                "replied_prs": [],
+                "non_replied_prs": [],
+                "monthly_approvals": { "month": "", "approvals": {}, "spent_down": false },
+            });
+        }
+        author["in_flight"] = json!([{
+            "reviews": [{
+                "pr": pr(1),
+                "reviewer": "mona",
+                "id": review,
+            }],
+            "bonus": {
+                "receiver_email": receiver_email,
+                "amount": 2,
+                "hashtag": "#teamwork",
+                "reason": reason,
+            },
+            "started": started,
+        }]);
+        fs::write(
+            self.dir.path().join("state.json"),
+            serde_json::to_string(&state).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn author_state(&self, key: &str) -> Vec<Value> {
+        self.state()["authors"]["me"][key]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn search_requests(&self) -> usize {
+        self.github
+            .server
+            .requests()
+            .iter()
+            .filter(|request| request.path == "/search/issues")
+            .count()
+    }
+
+    /// Bonus posts, including failed ones.
+    fn bonus_requests(&self) -> usize {
+        self.bonusly
+            .server
+            .requests()
+            .iter()
+            .filter(|request| request.method == "POST" && request.path == "/bonuses")
+            .count()
+    }
+}
+
+fn pr(number: i64) -> github::PullRequest {
+    github::PullRequest {
+        org: "acme".to_owned(),
+        repo: "widgets".to_owned(),
+        number,
+    }
+}
+
+fn sorted(mut sent: Vec<(String, u64)>) -> Vec<(String, u64)> {
+    sent.sort();
+    sent
+}
+
+fn mona_and_hubot(harness: &Harness) {
+    harness.github.add_user("mona", Some("Mona Lisa"));
+    harness.github.add_user("hubot", Some("Hubot Robot"));
+    harness.bonusly.add_user("mona@example.com", "Mona Lisa");
+    harness.bonusly.add_user("hubot@example.com", "Hubot Robot");
+}
+
+#[tokio::test(start_paused = true)]
+async fn bonuses_are_posted_once_across_cycles() {
+    let harness = Harness::new("once");
+    mona_and_hubot(&harness);
+    for number in 1..=3 {
+        harness.github.add_pr("me", &pr(number));
+        harness.github.approve(&pr(number), "mona");
+    }
+    harness.github.approve(&pr(2), "hubot");
+    harness.github.add_review(&pr(3), "hubot", "COMMENTED");
+    // Someone else's PR.
+    harness.github.add_pr("someone-else", &pr(4));
+    harness.github.approve(&pr(4), "mona");
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    // Three PRs take two pages of search results.
+    assert_eq!(harness.search_requests(), 2);
+    prg.reply_all().await.unwrap();
+
+    assert_eq!(
+        sorted(harness.bonusly.sent()),
+        vec![
+            ("hubot@example.com".to_owned(), 2),
+            ("mona@example.com".to_owned(), 2),
+            ("mona@example.com".to_owned(), 2),
+            ("mona@example.com".to_owned(), 2),
+        ]
+    );
+    assert_eq!(harness.author_state("replied_prs").len(), 4);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 0);
+    assert_eq!(
+        harness.state()["authors"]["me"]["monthly_approvals"]["approvals"]["mona@example.com"],
+        3
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn batched_bonus_marks_every_review_replied() {
+    let harness = Harness::with_config("batch", "batch_bonuses = true");
+    mona_and_hubot(&harness);
+    for number in 1..=3 {
+        harness.github.add_pr("me", &pr(number));
+    }
+    harness.github.approve(&pr(1), "mona");
+    harness.github.approve(&pr(2), "mona");
+    harness.github.approve(&pr(3), "hubot");
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    prg.reply_all().await.unwrap();
+
+    assert_eq!(
+        sorted(harness.bonusly.sent()),
+        vec![
+            ("hubot@example.com".to_owned(), 2),
+            ("mona@example.com".to_owned(), 4),
+        ]
+    );
+    assert_eq!(harness.author_state("replied_prs").len(), 3);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 0);
+    assert_eq!(
+        harness.state()["authors"]["me"]["monthly_approvals"]["approvals"]["mona@example.com"],
+        2
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn review_comments_are_counted_across_pages() {
+    let harness = Harness::with_config(
+        "review-comments",
+        r#"
+        [amount]
+        max = 10
+
+        [[amount.rules]]
+        min_review_comments = 3
+        add = 1
+        "#,
+    );
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.github.add_review_comments(&pr(1), "mona", 3);
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    assert_eq!(
+        harness.bonusly.sent(),
+        vec![("mona@example.com".to_owned(), 3)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_bonus_is_retried_next_cycle() {
+    let harness = Harness::new("retry");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.bonusly.fail_next_bonuses(1);
+
+    let mut prg = harness.program().await;
+    let err = prg.reply_all().await.unwrap_err();
+    assert!(err.downcast_ref::<SendErrors>().is_some(), "{err:?}");
+    assert_eq!(harness.bonusly.sent(), vec![]);
+    assert_eq!(harness.author_state("replied_prs").len(), 0);
+    // A server error doesn't mean the bonus wasn't sent.
+    assert_eq!(harness.author_state("in_flight").len(), 1);
+
+    prg.reply_all().await.unwrap();
+    assert_eq!(
+        harness.bonusly.sent(),
+        vec![("mona@example.com".to_owned(), 2)]
+    );
+    assert_eq!(harness.github.single_review_requests(), 1);
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn server_error_leaves_bonus_in_flight() {
+    let harness = Harness::new("server-error");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.bonusly.fail_next_bonuses(2);
+
+    let mut prg = harness.program().await;
+    let err = prg.reply_all().await.unwrap_err();
+    assert!(err.downcast_ref::<SendErrors>().is_some(), "{err:?}");
+    // The bonus may have been sent before the error, so it isn't retried.
+    assert_eq!(harness.bonus_requests(), 1);
+    assert_eq!(harness.author_state("in_flight").len(), 1);
+    assert_eq!(harness.author_state("replied_prs").len(), 0);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn rate_limited_bonus_waits_for_retry_after() {
+    let harness = Harness::new("retry-after");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.bonusly.rate_limit_next_bonus(120);
+
+    let mut prg = harness.program().await;
+    let start = tokio::time::Instant::now();
+    prg.reply_all().await.unwrap();
+    // Much longer than the backoff would have been.
+    assert!(start.elapsed() >= std::time::Duration::from_secs(120));
+    assert_eq!(harness.bonus_requests(), 2);
+    assert_eq!(harness.bonusly.sent().len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn rejected_bonus_is_not_retried() {
+    let harness = Harness::new("rejected");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.bonusly.reject_next_bonus("Receiver not found");
+
+    let mut prg = harness.program().await;
+    let err = prg.reply_all().await.unwrap_err();
+    assert!(err.downcast_ref::<SendErrors>().is_some(), "{err:?}");
+    assert_eq!(harness.bonus_requests(), 1);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn exhausted_search_rate_limit_is_waited_out() {
+    let harness = Harness::new("rate-limit");
+    mona_and_hubot(&harness);
+    for number in 1..=3 {
+        harness.github.add_pr("me", &pr(number));
+        harness.github.approve(&pr(number), "mona");
+    }
+    harness.github.exhaust_search_rate_limit(60);
+
+    let mut prg = harness.program().await;
+    let start = tokio::time::Instant::now();
+    prg.reply_all().await.unwrap();
+    // The second page of results waits for the reset.
+    assert!(start.elapsed() >= std::time::Duration::from_secs(59));
+    assert_eq!(harness.search_requests(), 2);
+    assert_eq!(harness.bonusly.sent().len(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn secondary_rate_limit_is_reported() {
+    let harness = Harness::new("secondary-rate-limit");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.github.secondary_rate_limit_next_search();
+
+    let mut prg = harness.program().await;
+    let err = prg.reply_all().await.unwrap_err();
+    match err.downcast_ref::<github::RateLimited>() {
+        Some(github::RateLimited::Secondary { retry_after, .. }) => {
+            assert_eq!(*retry_after, Some(std::time::Duration::from_secs(60)));
+        }
+        _ => panic!("Expected a secondary rate limit error: {err:?}"),
+    }
+    assert_eq!(harness.bonusly.sent(), vec![]);
+
+    prg.reply_all().await.unwrap();
+    assert_eq!(harness.bonusly.sent().len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn bonus_in_flight_is_not_resent_if_it_was_sent() {
+    let harness = Harness::new("in-flight-sent");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    let review = harness.github.approve(&pr(1), "mona");
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    drop(prg);
+    let reason = harness.bonusly.bonuses()[0]["reason"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    // As if the program died before recording that the bonus was sent.
+    harness.set_in_flight(review, "mona@example.com", &reason, "2022-03-01T12:00:00Z");
+    let mut state = harness.state();
+    state["authors"]["me"]["replied_prs"] = json!([]);
+    state["authors"]["me"]["monthly_approvals"]["approvals"] = json!({});
+    fs::write(
+        harness.dir.path().join("state.json"),
+        serde_json::to_string(&state).unwrap(),
+    )
+    .unwrap();
+
+    let mut prg = harness.program().await;
+    assert_eq!(harness.author_state("in_flight").len(), 0);
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+    prg.reply_all().await.unwrap();
+    assert_eq!(
+        harness.bonusly.sent(),
+        vec![("mona@example.com".to_owned(), 2)]
+    );
+    assert_eq!(
+        harness.state()["authors"]["me"]["monthly_approvals"]["approvals"]["mona@example.com"],
+        1
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn bonus_in_flight_is_resent_if_it_was_not_sent() {
+    let harness = Harness::new("in-flight-unsent");
+    mona_and_hubot(&harness);
+    // Write the state file before the review exists.
+    drop(harness.program().await);
+    harness.github.add_pr("me", &pr(1));
+    let review = harness.github.approve(&pr(1), "mona");
+    harness.set_in_flight(
+        review,
+        "mona@example.com",
+        "thanks!",
+        "2022-03-01T12:00:00Z",
+    );
+
+    let mut prg = harness.program().await;
+    assert_eq!(harness.author_state("in_flight").len(), 0);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 1);
+    prg.reply_all().await.unwrap();
+    assert_eq!(
+        harness.bonusly.sent(),
+        vec![("mona@example.com".to_owned(), 2)]
+    );
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 0);
+}
+
+/// Send a bonus for a review, then pretend the same bonus was put in flight
+/// again `minutes` after it was sent, and start the program.
+async fn resend_in_flight(harness: &Harness, minutes: i64) -> Program {
+    mona_and_hubot(harness);
+    harness.github.add_pr("me", &pr(1));
+    let review = harness.github.approve(&pr(1), "mona");
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    drop(prg);
+    let reason = harness.bonusly.bonuses()[0]["reason"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+    let started = (chrono::Utc::now() + chrono::Duration::minutes(minutes)).to_rfc3339();
+    harness.set_in_flight(review, "mona@example.com", &reason, &started);
+    let mut state = harness.state();
+    state["authors"]["me"]["replied_prs"] = json!([]);
+    fs::write(
+        harness.dir.path().join("state.json"),
+        serde_json::to_string(&state).unwrap(),
+    )
+    .unwrap();
+
+    harness.program().await
+}
+
+#[tokio::test(start_paused = true)]
+async fn bonus_in_flight_is_matched_despite_clock_skew() {
+    let harness = Harness::new("in-flight-skew");
+    // Bonusly's clock is a few minutes behind ours.
+    let mut prg = resend_in_flight(&harness, 3).await;
+    assert_eq!(harness.author_state("in_flight").len(), 0);
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+    prg.reply_all().await.unwrap();
+    assert_eq!(harness.bonusly.sent().len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn bonus_in_flight_is_not_matched_by_a_much_earlier_bonus() {
+    let harness = Harness::new("in-flight-earlier");
+    let mut prg = resend_in_flight(&harness, 60).await;
+    assert_eq!(harness.author_state("in_flight").len(), 0);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 1);
+    prg.reply_all().await.unwrap();
+    assert_eq!(harness.bonusly.sent().len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn unmatched_reviewer_is_rewarded_after_resolving() {
+    let harness = Harness::new("resolve");
+    harness.github.add_user("octocat", Some("The Octocat"));
+    harness.bonusly.add_user("zed@example.com", "Zed Zulu");
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "octocat");
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    assert_eq!(harness.bonusly.sent(), vec![]);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 1);
+    assert!(harness.state()["unmatched_candidates"]
+        .as_object()
+        .unwrap()
+        .contains_key("octocat"));
+
+    prg.resolve_reviewer("octocat", Resolution::Email("zed@example.com".to_owned()))
+        .await
+        .unwrap();
+    prg.reply_all().await.unwrap();
+    assert_eq!(
+        harness.bonusly.sent(),
+        vec![("zed@example.com".to_owned(), 2)]
+    );
+    assert_eq!(
+        harness.state()["learned_emails"]["octocat"],
+        "zed@example.com"
+    );
+    assert_eq!(harness.author_state("non_replied_prs").len(), 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn giving_balance_is_respected() {
+    let harness = Harness::new("budget");
+    mona_and_hubot(&harness);
+    harness.bonusly.set_giving_balance(3);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    harness.github.add_pr("me", &pr(2));
+    harness.github.approve(&pr(2), "hubot");
+
+    let mut prg = harness.program().await;
+    let err = prg.reply_all().await.unwrap_err();
+    assert!(err.downcast_ref::<SendErrors>().is_some(), "{err:?}");
+    assert_eq!(harness.bonusly.sent().len(), 1);
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+    assert_eq!(harness.author_state("non_replied_prs").len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn state_survives_restarts() {
+    let harness = Harness::new("restart");
+    mona_and_hubot(&harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    drop(prg);
+
+    harness.github.add_pr("me", &pr(2));
+    harness.github.approve(&pr(2), "hubot");
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+
+    assert_eq!(
+        sorted(harness.bonusly.sent()),
+        vec![
+            ("hubot@example.com".to_owned(), 2),
+            ("mona@example.com".to_owned(), 2),
+        ]
+    );
+    assert_eq!(harness.author_state("replied_prs").len(), 2);
+    assert!(harness.dir.path().join("state.json.bak").exists());
+}
+
+/// Send a bonus for `pr(1)`, and back up the state file recording it.
+async fn send_and_back_up(harness: &Harness) {
+    mona_and_hubot(harness);
+    harness.github.add_pr("me", &pr(1));
+    harness.github.approve(&pr(1), "mona");
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    drop(prg);
+    fs::copy(
+        harness.dir.path().join("state.json"),
+        harness.dir.path().join("state.json.bak"),
+    )
+    .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn corrupt_state_file_falls_back_to_backup() {
+    let harness = Harness::new("corrupt-state");
+    send_and_back_up(&harness).await;
+    fs::write(
+        harness.dir.path().join("state.json"),
+        "{\"version\": 5, \"authors\"",
+    )
+    .unwrap();
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    assert_eq!(harness.bonusly.sent().len(), 1);
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn missing_state_file_falls_back_to_backup() {
+    let harness = Harness::new("missing-state");
+    send_and_back_up(&harness).await;
+    fs::remove_file(harness.dir.path().join("state.json")).unwrap();
+
+    let mut prg = harness.program().await;
+    prg.reply_all().await.unwrap();
+    assert_eq!(harness.bonusly.sent().len(), 1);
+    assert_eq!(harness.author_state("replied_prs").len(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn corrupt_state_file_and_backup_is_an_error() {
+    let harness = Harness::new("corrupt-backup");
+    send_and_back_up(&harness).await;
+    fs::write(harness.dir.path().join("state.json"), "not json").unwrap();
+    fs::write(harness.dir.path().join("state.json.bak"), "not json either").unwrap();
+
+    let err = harness.try_program().await.err().unwrap();
+    assert!(
+        format!("{err:?}").contains("Failed to read state from"),
+        "{err:?}"
+    );
+    assert_eq!(harness.bonusly.sent().len(), 1);
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use std::fs;
+
+use chrono::{Duration, Utc};
+use pretty_assertions::assert_eq;
+
+use cherries_4_prs::fake::{FakeBonusly, FakeGitHub};
+use cherries_4_prs::{github, Backfill, Config, Credentials, Program, SendErrors};
+use common::TestDir;
+
+fn pr(number: i64) -> github::PullRequest {
+    github::PullRequest {
+        org: "acme".to_owned(),
+        repo: "widgets".to_owned(),
+        number,
+    }
+}
+
+async fn program(
+    config: Config,
+    github: FakeGitHub,
+    bonusly: FakeBonusly,
+) -> Program<FakeGitHub, FakeBonusly> {
+    let credentials = Credentials {
+        github,
+        bonusly,
+        authors: Default::default(),
+    };
+    Program::new(config, credentials, false).await.unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn approvals_are_rewarded_once() {
+    let github = FakeGitHub::new();
+    github.add_user("mona", Some("Mona Lisa"), None);
+    github.approve(&pr(1), "mona");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    let dir = TestDir::new("once");
+    let mut prg = program(dir.config("", ""), github, bonusly).await;
+
+    prg.reply_all().await.unwrap();
+    let sent = prg.credentials.bonusly.sent();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].receiver_email, "mona@example.com");
+    assert_eq!(sent[0].amount, 2);
+    assert_eq!(sent[0].hashtag, "#teamwork");
+
+    prg.reply_all().await.unwrap();
+    assert_eq!(prg.credentials.bonusly.sent().len(), 1);
+    assert_eq!(prg.status().replied_reviews, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn unmatched_reviewers_are_left_pending() {
+    let github = FakeGitHub::new();
+    github.add_user("octocat", Some("The Octocat"), None);
+    github.approve(&pr(1), "octocat");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    let dir = TestDir::new("pending");
+    let mut prg = program(dir.config("", ""), github, bonusly).await;
+
+    prg.reply_all().await.unwrap();
+    assert_eq!(prg.credentials.bonusly.sent().len(), 0);
+    assert_eq!(prg.status().pending_reviews, 1);
+    assert_eq!(prg.unresolved_reviewers()[0].login, "octocat");
+}
+
+#[tokio::test(start_paused = true)]
+async fn bonuses_over_budget_are_not_sent() {
+    let github = FakeGitHub::new();
+    github.add_user("mona", Some("Mona Lisa"), None);
+    github.approve(&pr(1), "mona");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    bonusly.set_giving_balance(Some(1));
+    let dir = TestDir::new("budget");
+    let mut prg = program(dir.config("", ""), github, bonusly).await;
+
+    let err = prg.reply_all().await.unwrap_err();
+    assert!(err.downcast_ref::<SendErrors>().is_some(), "{err:?}");
+    assert_eq!(prg.credentials.bonusly.sent().len(), 0);
+    assert_eq!(prg.status().pending_reviews, 1);
+}
+
+/// A backfill window around now, which is when the fakes submit reviews.
+fn backfill() -> Backfill {
+    Backfill::new(
+        Utc::now() - Duration::days(1),
+        Some(Utc::now() + Duration::days(1)),
+    )
+    .unwrap()
+}
+
+#[tokio::test(start_paused = true)]
+async fn backfill_skips_replied_reviews() {
+    let github = FakeGitHub::new();
+    github.add_user("mona", Some("Mona Lisa"), None);
+    github.approve(&pr(1), "mona");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    let dir = TestDir::new("backfill-replied");
+    let mut prg = program(dir.config("", ""), github, bonusly).await;
+    prg.reply_all().await.unwrap();
+    prg.credentials.github.approve(&pr(2), "mona");
+
+    let plans = prg.plan_backfill(&backfill()).await.unwrap();
+    assert_eq!(plans.len(), 1);
+    assert_eq!(plans[0].reviews.len(), 1);
+    assert_eq!(plans[0].total(), 2);
+
+    prg.send_backfill(plans).await.unwrap();
+    assert_eq!(prg.credentials.bonusly.sent().len(), 2);
+    assert_eq!(prg.status().replied_reviews, 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn backfill_leaves_bonuses_over_budget_pending() {
+    let github = FakeGitHub::new();
+    github.add_user("mona", Some("Mona Lisa"), None);
+    github.add_user("hubot", Some("Hubot Robot"), None);
+    github.approve(&pr(1), "mona");
+    github.approve(&pr(2), "hubot");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    bonusly.add_user("hubot@example.com", "Hubot Robot");
+    bonusly.set_giving_balance(Some(3));
+    let dir = TestDir::new("backfill-budget");
+    let mut prg = program(dir.config("", ""), github, bonusly).await;
+
+    let plans = prg.plan_backfill(&backfill()).await.unwrap();
+    assert_eq!(plans[0].giving_balance, Some(3));
+    assert_eq!(plans[0].total(), 4);
+
+    let err = prg.send_backfill(plans).await.unwrap_err();
+    assert!(err.downcast_ref::<SendErrors>().is_some(), "{err:?}");
+    assert_eq!(prg.credentials.bonusly.sent().len(), 1);
+    assert_eq!(prg.status().replied_reviews, 1);
+    assert_eq!(prg.status().pending_reviews, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dry_runs_change_nothing() {
+    let dir = TestDir::new("dry-run");
+    let config = dir.config("", "");
+    let github = FakeGitHub::new();
+    github.add_user("mona", Some("Mona Lisa"), None);
+    github.approve(&pr(1), "mona");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    let credentials = {
+        let mut prg = program(config.clone(), github, bonusly).await;
+        prg.reply_all().await.unwrap();
+        prg.credentials
+    };
+    credentials.github.approve(&pr(2), "mona");
+    let bonusly = FakeBonusly::new("me@example.com");
+    bonusly.add_user("mona@example.com", "Mona Lisa");
+    let credentials = Credentials {
+        bonusly,
+        ..credentials
+    };
+    let before = fs::read(&config.state_path).unwrap();
+
+    let mut prg = Program::new(config.clone(), credentials, true)
+        .await
+        .unwrap();
+    prg.reply_all().await.unwrap();
+    assert_eq!(prg.credentials.bonusly.sent(), vec![]);
+    assert_eq!(prg.status().replied_reviews, 1);
+    assert_eq!(fs::read(&config.state_path).unwrap(), before);
+}
+
+#[tokio::test(start_paused = true)]
+async fn dry_runs_do_not_lock_the_state_file() {
+    let dir = TestDir::new("lock");
+    let config = dir.config("", "");
+    let _running = program(
+        config.clone(),
+        FakeGitHub::new(),
+        FakeBonusly::new("me@example.com"),
+    )
+    .await;
+
+    let credentials = || Credentials {
+        github: FakeGitHub::new(),
+        bonusly: FakeBonusly::new("me@example.com"),
+        authors: Default::default(),
+    };
+    let err = Program::new(config.clone(), credentials(), false)
+        .await
+        .err()
+        .expect("State file should be locked");
+    assert!(err.to_string().contains("is locked"), "{err:?}");
+    Program::new(config, credentials(), true).await.unwrap();
+}
@@ -0,0 +1,89 @@
+use cherries_4_prs::github::{self, glob_matches};
+
+fn config(toml: &str) -> github::Config {
+    toml::de::from_str(toml).unwrap()
+}
+
+#[test]
+fn globs_match_whole_names() {
+    assert!(glob_matches("acme/*", "acme/widgets"));
+    assert!(glob_matches("*/docs", "acme/docs"));
+    assert!(glob_matches("acme/*-bot?", "acme/deps-bot2"));
+    assert!(glob_matches("ACME/Docs", "acme/docs"));
+    assert!(glob_matches("*", "acme/docs"));
+    assert!(!glob_matches("acme/*", "other/widgets"));
+    assert!(!glob_matches("acme/doc", "acme/docs"));
+    assert!(!glob_matches("acme/docs?", "acme/docs"));
+}
+
+#[test]
+fn excluded_repos_are_matched_by_owner_and_name() {
+    let config = config(
+        r#"
+        user = "me"
+        orgs = ["acme", "acme-labs"]
+        exclude_repos = ["acme/docs", "*/dependabot-*"]
+        "#,
+    );
+    config.validate().unwrap();
+    assert!(config.is_excluded("acme", "docs"));
+    assert!(config.is_excluded("acme-labs", "dependabot-config"));
+    assert!(!config.is_excluded("acme-labs", "docs"));
+    assert!(!config.is_excluded("acme", "widgets"));
+}
+
+#[test]
+fn something_to_search_is_required() {
+    assert!(config(r#"user = "me""#).validate().is_err());
+    config(
+        r#"
+        user = "me"
+        org = "acme"
+        "#,
+    )
+    .validate()
+    .unwrap();
+    config(
+        r#"
+        user = "me"
+        repos = ["me/dotfiles"]
+        "#,
+    )
+    .validate()
+    .unwrap();
+}
+
+#[test]
+fn repos_must_have_an_owner() {
+    let config = config(
+        r#"
+        user = "me"
+        repos = ["dotfiles"]
+        "#,
+    );
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn api_url_gets_a_trailing_slash() {
+    let config = config(
+        r#"
+        user = "me"
+        org = "acme"
+        api_url = "https://github.example.com/api/v3"
+        "#,
+    );
+    assert_eq!(
+        config.api_url().unwrap().as_str(),
+        "https://github.example.com/api/v3/"
+    );
+    assert_eq!(
+        config
+            .api_url()
+            .unwrap()
+            .join("repos/acme/widgets")
+            .unwrap()
+            .as_str(),
+        "https://github.example.com/api/v3/repos/acme/widgets"
+    );
+}
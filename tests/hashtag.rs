@@ -0,0 +1,152 @@
+use pretty_assertions::assert_eq;
+
+use cherries_4_prs::github;
+use cherries_4_prs::hashtag::{self, Context};
+
+fn config(toml: &str) -> hashtag::Config {
+    toml::de::from_str(toml).unwrap()
+}
+
+fn company() -> Vec<String> {
+    ["#teamwork", "#quality", "#communication"]
+        .iter()
+        .map(|hashtag| (*hashtag).to_owned())
+        .collect()
+}
+
+fn pr(repo: &str) -> github::PullRequest {
+    github::PullRequest {
+        org: "acme".to_owned(),
+        repo: repo.to_owned(),
+        number: 1,
+    }
+}
+
+#[test]
+fn round_robin_wraps_around() {
+    let config = config(
+        r##"
+        policy = "round-robin"
+        hashtags = ["#teamwork", "quality"]
+        "##,
+    );
+    let mut round_robin = 0;
+    let chosen: Vec<String> = (0..5)
+        .map(|_| {
+            config
+                .choose(Context::default(), &company(), &mut round_robin)
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(
+        chosen,
+        [
+            "#teamwork",
+            "#quality",
+            "#teamwork",
+            "#quality",
+            "#teamwork"
+        ]
+    );
+    assert_eq!(round_robin, 5);
+}
+
+#[test]
+fn round_robin_skips_invalid_hashtags() {
+    let config = config(
+        r##"
+        policy = "round-robin"
+        hashtags = ["#teamwork", "#gone", "#quality"]
+        "##,
+    );
+    let mut round_robin = 1;
+    assert_eq!(
+        config
+            .choose(Context::default(), &company(), &mut round_robin)
+            .unwrap(),
+        "#quality"
+    );
+}
+
+#[test]
+fn weighted_ignores_invalid_hashtags() {
+    let config = config(
+        r##"
+        policy = "weighted"
+        weights = { "#gone" = 100, "#quality" = 1, "#teamwork" = 0 }
+        "##,
+    );
+    for _ in 0..20 {
+        assert_eq!(
+            config
+                .choose(Context::default(), &company(), &mut 0)
+                .unwrap(),
+            "#quality"
+        );
+    }
+}
+
+#[test]
+fn no_valid_hashtag_is_an_error() {
+    let config = config(
+        r##"
+        policy = "fixed"
+        hashtag = "#gone"
+        "##,
+    );
+    let err = config
+        .choose(Context::default(), &company(), &mut 0)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("No valid Bonusly hashtag"), "{err}");
+
+    let err = hashtag::Config::default()
+        .choose(Context::default(), &[], &mut 0)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("(none)"), "{err}");
+}
+
+#[test]
+fn matching_rule_takes_precedence() {
+    let config = config(
+        r##"
+        policy = "fixed"
+        hashtag = "#teamwork"
+
+        [[rules]]
+        repo = "acme/docs"
+        hashtag = "#communication"
+        "##,
+    );
+    let docs = pr("docs");
+    let widgets = pr("widgets");
+    let choose = |pr| {
+        config
+            .choose(
+                Context {
+                    pr: Some(pr),
+                    details: None,
+                },
+                &company(),
+                &mut 0,
+            )
+            .unwrap()
+    };
+    assert_eq!(choose(&docs), "#communication");
+    assert_eq!(choose(&widgets), "#teamwork");
+}
+
+#[test]
+fn unknown_configured_hashtags_are_rejected() {
+    let config = config(
+        r##"
+        policy = "fixed"
+        hashtag = "#gone"
+        "##,
+    );
+    let err = config.validate(&company()).unwrap_err().to_string();
+    assert!(err.contains("#gone"), "{err}");
+    // Before the company's hashtags are fetched, anything goes.
+    config.validate(&[]).unwrap();
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::collections::HashMap;
+
+use cherries_4_prs::fake::bonusly_user;
+use cherries_4_prs::{bonusly, github, rank_bonusly_users, BonuslyMatch};
+use common::TestDir;
+
+fn github_user(login: &str, name: Option<&str>) -> github::User {
+    github::User {
+        id: 1,
+        login: login.to_owned(),
+        email: None,
+        name: name.map(str::to_owned),
+    }
+}
+
+/// Look up `find`'s email with the default match threshold.
+fn find(name: &str, users: &[bonusly::User], find: &github::User) -> BonuslyMatch {
+    let dir = TestDir::new(name);
+    dir.config("", "")
+        .find_bonusly_email(users, &HashMap::new(), find)
+}
+
+fn found_email(found: BonuslyMatch) -> Option<String> {
+    match found {
+        BonuslyMatch::Found(candidate) => Some(candidate.email),
+        BonuslyMatch::Unresolved(_) => None,
+    }
+}
+
+#[test]
+fn partial_first_name_is_not_accepted() {
+    let users = [bonusly_user("jordan@example.com", "Jordan Smith")];
+    let dan = github_user("dsmith", Some("Dan Smith"));
+    let ranked = rank_bonusly_users(&users, &dan);
+    assert!(ranked.iter().all(|candidate| candidate.confidence < 0.8));
+    assert_eq!(found_email(find("partial-first", &users, &dan)), None);
+}
+
+#[test]
+fn short_login_is_not_accepted() {
+    let users = [bonusly_user("alice@example.com", "Alice Smith")];
+    let al = github_user("al", None);
+    let ranked = rank_bonusly_users(&users, &al);
+    assert!(ranked.iter().all(|candidate| candidate.confidence < 0.8));
+    assert_eq!(found_email(find("short-login", &users, &al)), None);
+}
+
+#[test]
+fn shortened_first_name_is_accepted() {
+    let users = [
+        bonusly_user("matthew@example.com", "Matthew Smith"),
+        bonusly_user("jordan@example.com", "Jordan Smith"),
+    ];
+    let matt = github_user("mattsmith", Some("Matt Smith"));
+    assert_eq!(
+        found_email(find("shortened", &users, &matt)).as_deref(),
+        Some("matthew@example.com")
+    );
+}
+
+#[test]
+fn login_made_from_name_is_accepted() {
+    let users = [
+        bonusly_user("dan@example.com", "Dan Smith"),
+        bonusly_user("jordan@example.com", "Jordan Smith"),
+    ];
+    let dsmith = github_user("dsmith", None);
+    assert_eq!(
+        found_email(find("login", &users, &dsmith)).as_deref(),
+        Some("dan@example.com")
+    );
+}
+
+#[test]
+fn equally_good_matches_are_ambiguous() {
+    let users = [
+        bonusly_user("daniel@example.com", "Daniel Smith"),
+        bonusly_user("danielle@example.com", "Danielle Smith"),
+    ];
+    let dan = github_user("dan", Some("Dan Smith"));
+    match find("ambiguous", &users, &dan) {
+        BonuslyMatch::Unresolved(candidates) => assert_eq!(candidates.len(), 2),
+        BonuslyMatch::Found(candidate) => panic!("Expected no match, got {candidate:?}"),
+    }
+}
+
+#[test]
+fn users_who_cannot_receive_are_skipped() {
+    let mut departed = bonusly_user("mona.old@example.com", "Mona Lisa");
+    departed.can_receive = false;
+    let users = [
+        departed,
+        bonusly_user("mona@example.com", "Mona Lisa Vito"),
+        bonusly_user("hubot@example.com", "Hubot Robot"),
+    ];
+    let mona = github_user("monalisa", Some("Mona Lisa"));
+    assert_eq!(
+        found_email(find("cannot-receive", &users, &mona)).as_deref(),
+        Some("mona@example.com")
+    );
+}
+
+#[test]
+fn first_name_spelled_differently_is_accepted() {
+    let users = [
+        bonusly_user("john@example.com", "John Smith"),
+        bonusly_user("jane@example.com", "Jane Smith"),
+    ];
+    let jon = github_user("jsmith2", Some("Jon Smith"));
+    assert_eq!(
+        found_email(find("spelling", &users, &jon)).as_deref(),
+        Some("john@example.com")
+    );
+}
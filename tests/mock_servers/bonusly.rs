@@ -0,0 +1,178 @@
+//! A stand-in for the Bonusly users, companies, and bonuses endpoints.
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use super::http::{Request, Response, Server};
+
+struct Data {
+    me: Value,
+    users: Vec<Value>,
+    hashtags: Vec<String>,
+    /// Bonuses successfully posted.
+    bonuses: Vec<Value>,
+    /// Responses to upcoming bonus posts, instead of posting them.
+    failures: VecDeque<Response>,
+}
+
+pub struct MockBonusly {
+    pub server: Server,
+    data: Arc<Mutex<Data>>,
+}
+
+fn user(email: &str, full_name: &str) -> Value {
+    let (first_name, last_name) = full_name.split_once(' ').unwrap_or((full_name, ""));
+    json!({
+        "id": email,
+        "short_name": email.split('@').next().unwrap(),
+        "full_name": full_name,
+        "display_name": full_name,
+        "first_name": first_name,
+        "last_name": last_name,
+        "email": email,
+        "can_receive": true,
+    })
+}
+
+fn ok(result: Value) -> Response {
+    Response::json(200, json!({ "success": true, "result": result }))
+}
+
+fn error(status: u16, message: &str) -> Response {
+    Response::json(status, json!({ "success": false, "message": message }))
+}
+
+impl MockBonusly {
+    /// A company with one user, `my_email`, and the `#teamwork` hashtag.
+    pub fn start(my_email: &str) -> Self {
+        let me = user(my_email, "Me Myself");
+        let data = Arc::new(Mutex::new(Data {
+            users: vec![me.clone()],
+            me,
+            hashtags: vec!["#teamwork".to_owned()],
+            bonuses: Vec::new(),
+            failures: VecDeque::new(),
+        }));
+        let handler_data = Arc::clone(&data);
+        let server = Server::start(Arc::new(move |request: &Request| {
+            handle(&mut handler_data.lock().unwrap(), request)
+        }));
+        Self { server, data }
+    }
+
+    pub fn add_user(&self, email: &str, full_name: &str) {
+        self.data.lock().unwrap().users.push(user(email, full_name));
+    }
+
+    pub fn set_giving_balance(&self, balance: usize) {
+        self.data.lock().unwrap().me["giving_balance"] = json!(balance);
+    }
+
+    /// Fail the next `count` bonus posts with a server error.
+    pub fn fail_next_bonuses(&self, count: usize) {
+        let mut data = self.data.lock().unwrap();
+        for _ in 0..count {
+            data.failures.push_back(error(500, "Internal server error"));
+        }
+    }
+
+    /// Reject the next bonus post as invalid, with `message`.
+    pub fn reject_next_bonus(&self, message: &str) {
+        self.data
+            .lock()
+            .unwrap()
+            .failures
+            .push_back(error(422, message));
+    }
+
+    /// Rate limit the next bonus post, asking to retry after `seconds`.
+    pub fn rate_limit_next_bonus(&self, seconds: u64) {
+        self.data
+            .lock()
+            .unwrap()
+            .failures
+            .push_back(error(429, "Too many requests").header("Retry-After", seconds.to_string()));
+    }
+
+    /// The bonuses successfully posted, oldest first.
+    pub fn bonuses(&self) -> Vec<Value> {
+        self.data.lock().unwrap().bonuses.clone()
+    }
+
+    /// `(receiver_email, amount)` for each bonus successfully posted.
+    pub fn sent(&self) -> Vec<(String, u64)> {
+        self.bonuses()
+            .iter()
+            .map(|bonus| {
+                (
+                    bonus["receiver_email"].as_str().unwrap().to_owned(),
+                    bonus["amount"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn handle(data: &mut Data, request: &Request) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/users") => {
+            let skip: usize = request.query["skip"].parse().unwrap();
+            let limit: usize = request.query["limit"].parse().unwrap();
+            ok(json!(data
+                .users
+                .iter()
+                .skip(skip)
+                .take(limit)
+                .collect::<Vec<_>>()))
+        }
+        ("GET", "/users/me") => ok(data.me.clone()),
+        // Ignores `giver_email` and `start_time`; every bonus is ours and
+        // recent.
+        ("GET", "/bonuses") => {
+            let skip: usize = request.query["skip"].parse().unwrap();
+            let limit: usize = request.query["limit"].parse().unwrap();
+            ok(json!(data
+                .bonuses
+                .iter()
+                .enumerate()
+                .skip(skip)
+                .take(limit)
+                .map(|(index, bonus)| json!({
+                    "id": (index + 1).to_string(),
+                    "created_at": bonus["created_at"],
+                    "reason": format!("{} {}", bonus["reason"].as_str().unwrap(), bonus["hashtag"].as_str().unwrap()),
+                    "amount": bonus["amount"],
+                    "receivers": [{ "email": bonus["receiver_email"] }],
+                }))
+                .collect::<Vec<_>>()))
+        }
+        ("GET", "/companies/show") => ok(json!({ "company_hashtags": data.hashtags })),
+        ("POST", "/bonuses") => {
+            if let Some(failure) = data.failures.pop_front() {
+                return failure;
+            }
+            let mut bonus = request.json();
+            let receiver = bonus["receiver_email"].as_str().unwrap();
+            if !data.users.iter().any(|user| user["email"] == receiver) {
+                return error(422, "Receiver not found");
+            }
+            let amount = bonus["amount"].as_u64().unwrap();
+            if let Some(balance) = data.me["giving_balance"].as_u64() {
+                if amount > balance {
+                    return error(422, "You don't have enough to give");
+                }
+                data.me["giving_balance"] = json!(balance - amount);
+            }
+            bonus["created_at"] = json!(Utc::now().to_rfc3339());
+            data.bonuses.push(bonus.clone());
+            ok(json!({
+                "id": data.bonuses.len().to_string(),
+                "created_at": bonus["created_at"],
+                "reason": bonus["reason"],
+            }))
+        }
+        _ => error(404, "Not found"),
+    }
+}
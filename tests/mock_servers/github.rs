@@ -0,0 +1,308 @@
+//! A stand-in for the GitHub search, reviews, and users endpoints.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+
+use cherries_4_prs::github::PullRequest;
+
+use super::http::{Request, Response, Server};
+
+/// Search results and reviews are split into pages this big, so that
+/// pagination is exercised.
+const PAGE_SIZE: usize = 2;
+
+#[derive(Default)]
+struct Data {
+    /// PR author, and the PR.
+    prs: Vec<(String, PullRequest)>,
+    reviews: HashMap<PullRequest, Vec<Value>>,
+    review_comments: HashMap<PullRequest, Vec<Value>>,
+    users: HashMap<String, Value>,
+    next_id: u64,
+    /// Error for the next search, instead of searching.
+    search_error: Option<Response>,
+    /// When the search rate limit resets, to report as used up in the next
+    /// search's response.
+    search_exhausted_until: Option<i64>,
+}
+
+pub struct MockGitHub {
+    pub server: Server,
+    data: Arc<Mutex<Data>>,
+}
+
+impl MockGitHub {
+    pub fn start() -> Self {
+        let data = Arc::new(Mutex::new(Data::default()));
+        let handler_data = Arc::clone(&data);
+        let server = Server::start(Arc::new(move |request: &Request| {
+            handle(&mut handler_data.lock().unwrap(), request)
+        }));
+        Self { server, data }
+    }
+
+    pub fn add_user(&self, login: &str, name: Option<&str>) {
+        let mut data = self.data.lock().unwrap();
+        data.next_id += 1;
+        let user = json!({
+            "id": data.next_id,
+            "login": login,
+            "name": name,
+            "email": null,
+        });
+        data.users.insert(login.to_owned(), user);
+    }
+
+    /// Add a PR by `author`.
+    pub fn add_pr(&self, author: &str, pr: &PullRequest) {
+        let mut data = self.data.lock().unwrap();
+        data.prs.push((author.to_owned(), pr.clone()));
+    }
+
+    /// Add a review to a PR, returning its ID.
+    pub fn add_review(&self, pr: &PullRequest, reviewer: &str, state: &str) -> u64 {
+        let mut data = self.data.lock().unwrap();
+        data.next_id += 1;
+        let id = data.next_id;
+        let PullRequest { org, repo, number } = pr;
+        let review = json!({
+            "id": id,
+            "node_id": format!("review-{id}"),
+            "html_url": format!("https://github.com/{org}/{repo}/pull/{number}#pullrequestreview-{id}"),
+            "user": { "login": reviewer },
+            "state": state,
+            "submitted_at": "2022-03-01T12:00:00Z",
+        });
+        data.reviews.entry(pr.clone()).or_default().push(review);
+        id
+    }
+
+    pub fn approve(&self, pr: &PullRequest, reviewer: &str) -> u64 {
+        self.add_review(pr, reviewer, "APPROVED")
+    }
+
+    /// Add `count` review comments by `reviewer` to a PR.
+    pub fn add_review_comments(&self, pr: &PullRequest, reviewer: &str, count: usize) {
+        let mut data = self.data.lock().unwrap();
+        for _ in 0..count {
+            data.review_comments
+                .entry(pr.clone())
+                .or_default()
+                .push(json!({ "user": { "login": reviewer } }));
+        }
+    }
+
+    /// Report the search rate limit as used up for `seconds` in the next
+    /// search's response.
+    pub fn exhaust_search_rate_limit(&self, seconds: i64) {
+        self.data.lock().unwrap().search_exhausted_until =
+            Some(chrono::Utc::now().timestamp() + seconds);
+    }
+
+    /// Fail the next search with a secondary rate limit error.
+    pub fn secondary_rate_limit_next_search(&self) {
+        let response = Response::json(
+            403,
+            json!({
+                "message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.",
+                "documentation_url": "https://docs.github.com/rest/overview/resources-in-the-rest-api#secondary-rate-limits",
+            }),
+        )
+        .header("Retry-After", "60");
+        self.data.lock().unwrap().search_error = Some(response);
+    }
+
+    /// Requests for a single review, which are made for pending reviews.
+    pub fn single_review_requests(&self) -> usize {
+        self.server
+            .requests()
+            .iter()
+            .filter(|request| request.path.contains("/reviews/") && request.method == "GET")
+            .count()
+    }
+}
+
+fn handle(data: &mut Data, request: &Request) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["search", "issues"] => {
+            if let Some(error) = data.search_error.take() {
+                return error;
+            }
+            let response = search(data, request);
+            match data.search_exhausted_until.take() {
+                Some(reset) => response
+                    .header("X-RateLimit-Limit", "30")
+                    .header("X-RateLimit-Remaining", "0")
+                    .header("X-RateLimit-Reset", reset.to_string()),
+                None => response,
+            }
+        }
+        ["repos", org, repo, "pulls", number, "reviews"] => {
+            let reviews = find_reviews(data, org, repo, number);
+            paginate(request, reviews.to_vec())
+        }
+        ["repos", org, repo, "pulls", number, "reviews", id] => {
+            match find_reviews(data, org, repo, number)
+                .iter()
+                .find(|review| id.parse().is_ok_and(|id: u64| review["id"] == id))
+            {
+                Some(review) => Response::json(200, review.clone()),
+                None => Response::not_found(),
+            }
+        }
+        ["repos", org, repo, "pulls", number] => Response::json(
+            200,
+            json!({
+                "title": format!("PR {number}"),
+                "html_url": format!("https://github.com/{org}/{repo}/pull/{number}"),
+            }),
+        ),
+        ["repos", org, repo, "pulls", number, "comments"] => {
+            let pr = PullRequest {
+                org: (*org).to_owned(),
+                repo: (*repo).to_owned(),
+                number: number.parse().unwrap_or_default(),
+            };
+            let comments = data.review_comments.get(&pr).cloned().unwrap_or_default();
+            paginate(request, comments)
+        }
+        ["users", login] => match data.users.get(*login) {
+            Some(user) => Response::json(200, user.clone()),
+            None => Response::not_found(),
+        },
+        _ => Response::not_found(),
+    }
+}
+
+fn find_reviews<'a>(data: &'a Data, org: &str, repo: &str, number: &str) -> &'a [Value] {
+    let pr = PullRequest {
+        org: org.to_owned(),
+        repo: repo.to_owned(),
+        number: number.parse().unwrap_or_default(),
+    };
+    data.reviews.get(&pr).map(Vec::as_slice).unwrap_or_default()
+}
+
+/// PRs with an approved review that match the query's `author:`, `org:`, and
+/// `repo:` qualifiers. Other qualifiers are ignored.
+fn search(data: &Data, request: &Request) -> Response {
+    let query = request.query.get("q").cloned().unwrap_or_default();
+    let mut author = None;
+    let mut orgs = Vec::new();
+    let mut repos = Vec::new();
+    for term in query.split_whitespace() {
+        if let Some(value) = term.strip_prefix("author:") {
+            author = Some(value.to_owned());
+        } else if let Some(value) = term.strip_prefix("org:") {
+            orgs.push(value.to_owned());
+        } else if let Some(value) = term.strip_prefix("repo:") {
+            repos.push(value.to_owned());
+        }
+    }
+
+    let items = data
+        .prs
+        .iter()
+        .filter(|(pr_author, _)| author.as_ref().is_none_or(|author| author == pr_author))
+        .filter(|(_, pr)| {
+            orgs.contains(&pr.org) || repos.contains(&format!("{}/{}", pr.org, pr.repo))
+        })
+        .filter(|(_, pr)| {
+            data.reviews
+                .get(pr)
+                .is_some_and(|reviews| reviews.iter().any(|review| review["state"] == "APPROVED"))
+        })
+        .map(|(author, pr)| issue(author, pr))
+        .collect();
+    paginate(request, items)
+}
+
+/// A page of `items`, with a `Link` header for the next page. Arrays are
+/// wrapped like search results for `/search/` requests.
+fn paginate(request: &Request, items: Vec<Value>) -> Response {
+    let page: usize = request
+        .query
+        .get("page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1);
+    let start = (page - 1) * PAGE_SIZE;
+    let page_items: Vec<Value> = items.iter().skip(start).take(PAGE_SIZE).cloned().collect();
+    let body = if request.path.starts_with("/search/") {
+        json!({
+            "total_count": items.len(),
+            "incomplete_results": false,
+            "items": page_items,
+        })
+    } else {
+        Value::Array(page_items)
+    };
+    let response = Response::json(200, body);
+    if start + PAGE_SIZE < items.len() {
+        let mut query = request.query.clone();
+        query.insert("page".to_owned(), (page + 1).to_string());
+        let next = reqwest::Url::parse_with_params(
+            &format!("http://{}{}", request.host, request.path),
+            &query,
+        )
+        .unwrap();
+        response.header("Link", format!("<{next}>; rel=\"next\""))
+    } else {
+        response
+    }
+}
+
+/// A search result for a PR, with every field `octocrab` requires.
+fn issue(author: &str, pr: &PullRequest) -> Value {
+    let PullRequest { org, repo, number } = pr;
+    let api = format!("https://api.github.com/repos/{org}/{repo}");
+    json!({
+        "id": number,
+        "node_id": format!("pr-{number}"),
+        "url": format!("{api}/issues/{number}"),
+        "repository_url": api,
+        "labels_url": format!("{api}/issues/{number}/labels"),
+        "comments_url": format!("{api}/issues/{number}/comments"),
+        "events_url": format!("{api}/issues/{number}/events"),
+        "html_url": format!("https://github.com/{org}/{repo}/pull/{number}"),
+        "number": number,
+        "state": "open",
+        "title": format!("PR {number}"),
+        "body": null,
+        "user": user(author),
+        "labels": [],
+        "assignees": [],
+        "author_association": "MEMBER",
+        "locked": false,
+        "comments": 0,
+        "created_at": "2022-03-01T09:00:00Z",
+        "updated_at": "2022-03-01T12:00:00Z",
+    })
+}
+
+/// A user as embedded in other objects, with every field `octocrab`
+/// requires.
+fn user(login: &str) -> Value {
+    let api = format!("https://api.github.com/users/{login}");
+    json!({
+        "login": login,
+        "id": 1,
+        "node_id": format!("user-{login}"),
+        "avatar_url": "https://avatars.githubusercontent.com/u/1",
+        "gravatar_id": "",
+        "url": api,
+        "html_url": format!("https://github.com/{login}"),
+        "followers_url": format!("{api}/followers"),
+        "following_url": format!("{api}/following"),
+        "gists_url": format!("{api}/gists"),
+        "starred_url": format!("{api}/starred"),
+        "subscriptions_url": format!("{api}/subscriptions"),
+        "organizations_url": format!("{api}/orgs"),
+        "repos_url": format!("{api}/repos"),
+        "events_url": format!("{api}/events"),
+        "received_events_url": format!("{api}/received_events"),
+        "type": "User",
+        "site_admin": false,
+    })
+}
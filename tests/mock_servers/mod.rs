@@ -0,0 +1,9 @@
+//! Local HTTP stand-ins for the GitHub and Bonusly APIs.
+#![allow(dead_code)]
+
+mod bonusly;
+mod github;
+mod http;
+
+pub use self::bonusly::MockBonusly;
+pub use self::github::MockGitHub;
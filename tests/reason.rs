@@ -0,0 +1,117 @@
+use std::convert::TryFrom;
+
+use pretty_assertions::assert_eq;
+
+use cherries_4_prs::github;
+use cherries_4_prs::reason::{self, Template};
+
+const REVIEW_URL: &str = "https://github.com/acme/widgets/pull/7#pullrequestreview-1";
+
+fn pr() -> github::PullRequest {
+    github::PullRequest {
+        org: "acme".to_owned(),
+        repo: "widgets".to_owned(),
+        number: 7,
+    }
+}
+
+fn details() -> github::PullRequestDetails {
+    github::PullRequestDetails {
+        title: "Frobnicate the widgets".to_owned(),
+        html_url: "https://github.com/acme/widgets/pull/7".to_owned(),
+        additions: 10,
+        deletions: 5,
+        changed_files: 2,
+        labels: Vec::new(),
+    }
+}
+
+fn render(template: &str) -> String {
+    let pr = pr();
+    let details = details();
+    Template::try_from(template.to_owned())
+        .unwrap()
+        .render(&reason::Context {
+            pr: &pr,
+            details: Some(&details),
+            reviewer_name: "Mona Lisa",
+            review_url: REVIEW_URL,
+        })
+}
+
+fn parse_error(template: &str) -> String {
+    Template::try_from(template.to_owned())
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn placeholders_are_filled_in() {
+    assert_eq!(
+        render("thanks {reviewer_name} for {repo}#{pr_number} ({pr_title}, {lines_changed} lines)! {review_url}"),
+        format!(
+            "thanks Mona Lisa for acme/widgets#7 (Frobnicate the widgets, 15 lines)! {REVIEW_URL}"
+        )
+    );
+}
+
+#[test]
+fn doubled_braces_are_literal() {
+    assert_eq!(render("{{not a placeholder}}"), "{not a placeholder}");
+    assert_eq!(render("{{{repo}}}"), "{acme/widgets}");
+}
+
+#[test]
+fn unknown_placeholder_is_rejected() {
+    let err = parse_error("thanks {reviewer}!");
+    assert!(err.contains("Unknown placeholder `{reviewer}`"), "{err}");
+    assert!(err.contains("`{reviewer_name}`"), "{err}");
+}
+
+#[test]
+fn unclosed_brace_is_rejected() {
+    let err = parse_error("thanks {reviewer_name!");
+    assert!(err.contains("Unclosed `{`"), "{err}");
+}
+
+#[test]
+fn stray_closing_brace_is_rejected() {
+    let err = parse_error("thanks} {review_url}");
+    assert!(err.contains("Unmatched `}`"), "{err}");
+}
+
+#[test]
+fn review_url_is_added_if_missing() {
+    let config: reason::Config = toml::de::from_str(r#"templates = ["thanks!"]"#).unwrap();
+    let pr = pr();
+    let context = reason::Context {
+        pr: &pr,
+        details: None,
+        reviewer_name: "Mona Lisa",
+        review_url: REVIEW_URL,
+    };
+    assert_eq!(config.reason(&context), format!("thanks! {REVIEW_URL}"));
+}
+
+#[test]
+fn matching_rule_templates_are_used() {
+    let config: reason::Config = toml::de::from_str(
+        r#"
+        templates = ["default {review_url}"]
+
+        [[rules]]
+        repo = "acme/widgets"
+        templates = ["widgets {review_url}"]
+        "#,
+    )
+    .unwrap();
+    config.validate().unwrap();
+    let pr = pr();
+    let context = reason::Context {
+        pr: &pr,
+        details: None,
+        reviewer_name: "Mona Lisa",
+        review_url: REVIEW_URL,
+    };
+    assert_eq!(config.reason(&context), format!("widgets {REVIEW_URL}"));
+}
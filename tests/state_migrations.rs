@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use pretty_assertions::assert_eq;
+use serde_json::Value;
+
+use cherries_4_prs::{migrate_state, State, CURRENT_STATE_VERSION};
+
+/// A fixture for every state file version, oldest first.
+const FIXTURES: [&str; 6] = [
+    "state-v0.json",
+    "state-v1.json",
+    "state-v2.json",
+    "state-v3.json",
+    "state-v4.json",
+    "state-v5.json",
+];
+const CURRENT_FIXTURE: &str = FIXTURES[FIXTURES.len() - 1];
+
+/// The `github.user` the fixtures were written for.
+const MAIN_AUTHOR: &str = "me";
+
+/// Keys that moved into `authors.<MAIN_AUTHOR>` in version 4.
+const AUTHOR_KEYS: [&str; 3] = ["replied_prs", "non_replied_prs", "monthly_approvals"];
+
+fn fixture_path(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+fn fixture(name: &str) -> Value {
+    serde_json::from_str(&std::fs::read_to_string(fixture_path(name)).unwrap()).unwrap()
+}
+
+#[test]
+fn unversioned_state_is_migrated_to_current_version() {
+    let migrated = migrate_state(fixture("state-v0.json"), MAIN_AUTHOR).unwrap();
+
+    assert_eq!(migrated["version"], CURRENT_STATE_VERSION);
+    assert_eq!(migrated["unmatched_candidates"], serde_json::json!({}));
+    assert_eq!(migrated["learned_emails"], serde_json::json!({}));
+    assert_eq!(migrated["ignored_reviewers"], serde_json::json!([]));
+    assert_eq!(
+        migrated["authors"][MAIN_AUTHOR]["monthly_approvals"],
+        serde_json::json!({"month": "", "approvals": {}, "spent_down": false})
+    );
+    assert_eq!(migrated["hashtag_round_robin"], 0);
+
+    // Existing data is untouched, but may have moved to the main author.
+    let original = fixture("state-v0.json");
+    for (key, value) in original.as_object().unwrap() {
+        let migrated_value = if AUTHOR_KEYS.contains(&key.as_str()) {
+            assert_eq!(migrated.get(key), None, "{key} wasn't moved");
+            &migrated["authors"][MAIN_AUTHOR][key]
+        } else {
+            &migrated[key]
+        };
+        assert_eq!(migrated_value, value, "{key} changed during migration");
+    }
+}
+
+#[test]
+fn v1_state_is_migrated_to_current_version() {
+    let migrated = migrate_state(fixture("state-v1.json"), MAIN_AUTHOR).unwrap();
+    assert_eq!(migrated["version"], CURRENT_STATE_VERSION);
+    assert_eq!(
+        migrated["authors"][MAIN_AUTHOR]["monthly_approvals"],
+        serde_json::json!({"month": "", "approvals": {}, "spent_down": false})
+    );
+    assert_eq!(
+        migrated["ignored_reviewers"],
+        serde_json::json!(["dependabot"])
+    );
+    assert_eq!(migrated["hashtag_round_robin"], 0);
+}
+
+#[test]
+fn v2_state_is_migrated_to_current_version() {
+    let migrated = migrate_state(fixture("state-v2.json"), MAIN_AUTHOR).unwrap();
+    assert_eq!(migrated["version"], CURRENT_STATE_VERSION);
+    assert_eq!(migrated["hashtag_round_robin"], 0);
+    assert_eq!(
+        migrated["authors"][MAIN_AUTHOR]["monthly_approvals"]["approvals"],
+        serde_json::json!({"mona@example.com": 1})
+    );
+}
+
+#[test]
+fn v3_state_is_moved_to_main_author() {
+    let original = fixture("state-v3.json");
+    let migrated = migrate_state(original.clone(), MAIN_AUTHOR).unwrap();
+    assert_eq!(migrated["version"], CURRENT_STATE_VERSION);
+    let authors = migrated["authors"].as_object().unwrap();
+    assert_eq!(authors.keys().collect::<Vec<_>>(), vec![MAIN_AUTHOR]);
+    for key in AUTHOR_KEYS {
+        assert_eq!(migrated.get(key), None, "{key} wasn't moved");
+        assert_eq!(authors[MAIN_AUTHOR][key], original[key], "{key}");
+    }
+}
+
+#[test]
+fn v4_state_has_no_bonuses_in_flight() {
+    let migrated = migrate_state(fixture("state-v4.json"), MAIN_AUTHOR).unwrap();
+    assert_eq!(migrated["version"], CURRENT_STATE_VERSION);
+    for (author, state) in migrated["authors"].as_object().unwrap() {
+        assert_eq!(state["in_flight"], serde_json::json!([]), "{author}");
+    }
+}
+
+#[test]
+fn current_state_is_unchanged() {
+    let current = fixture(CURRENT_FIXTURE);
+    assert_eq!(
+        migrate_state(current.clone(), MAIN_AUTHOR).unwrap(),
+        current
+    );
+}
+
+#[test]
+fn newer_state_is_rejected() {
+    let mut state = fixture(CURRENT_FIXTURE);
+    state["version"] = (CURRENT_STATE_VERSION + 1).into();
+    let err = migrate_state(state, MAIN_AUTHOR).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("newer than the latest supported version"),
+        "{err}"
+    );
+}
+
+#[test]
+fn state_files_of_every_version_can_be_read() {
+    for name in FIXTURES {
+        let state = State::read_from_path(&fixture_path(name), MAIN_AUTHOR)
+            .unwrap_or_else(|err| panic!("Failed to read {name}: {err:?}"));
+        let written = serde_json::to_value(&state).unwrap();
+        assert_eq!(written["version"], CURRENT_STATE_VERSION, "{name}");
+        let main_author = &written["authors"][MAIN_AUTHOR];
+        assert_eq!(
+            main_author["replied_prs"].as_array().unwrap().len(),
+            1,
+            "{name}"
+        );
+        assert_eq!(
+            main_author["non_replied_prs"].as_array().unwrap().len(),
+            1,
+            "{name}"
+        );
+    }
+}